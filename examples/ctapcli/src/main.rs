@@ -25,8 +25,6 @@ static CFG: Lazy<Cfg> = Lazy::new(|| load_cfg());
 fn load_cfg() -> ctap_hid_fido2::Cfg {
     let mut cfg = Cfg::init();
     cfg.enable_log = false;
-    cfg.use_pre_bio_enrollment = true;
-    cfg.use_pre_credential_management = true;
     cfg
 }
 
@@ -1,11 +1,24 @@
+/*!
+Authenticator Data
+[https://www.w3.org/TR/webauthn/#sctn-authenticator-data](https://www.w3.org/TR/webauthn/#sctn-authenticator-data)
+*/
+
+use crate::cose::CoseKey;
+use crate::public_key::PublicKey;
 use crate::str_buf::StrBuf;
-use anyhow::Result;
+use crate::util;
+use anyhow::{anyhow, Result};
+use serde_cbor::Value;
+use std::collections::BTreeMap;
+use std::convert::TryInto;
 use std::fmt;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Flags {
     pub user_present_result: bool,
     pub user_verified_result: bool,
+    pub backup_eligibility: bool,
+    pub backup_state: bool,
     pub attested_credential_data_included: bool,
     pub extension_data_included: bool,
 }
@@ -15,6 +28,8 @@ impl Flags {
         let flags = Flags {
             user_present_result: matches!(byte & 0x01, 0x01),
             user_verified_result: matches!(byte & 0x04, 0x04),
+            backup_eligibility: matches!(byte & 0x08, 0x08),
+            backup_state: matches!(byte & 0x10, 0x10),
             attested_credential_data_included: matches!(byte & 0x40, 0x40),
             extension_data_included: matches!(byte & 0x80, 0x80),
         };
@@ -24,16 +39,22 @@ impl Flags {
     pub fn as_u8(&self) -> u8 {
         let mut ret = 0x0;
         if self.user_present_result {
-            ret = ret | 0x01;
+            ret |= 0x01;
         }
         if self.user_verified_result {
-            ret = ret | 0x04;
+            ret |= 0x04;
+        }
+        if self.backup_eligibility {
+            ret |= 0x08;
+        }
+        if self.backup_state {
+            ret |= 0x10;
         }
         if self.attested_credential_data_included {
-            ret = ret | 0x40;
+            ret |= 0x40;
         }
         if self.extension_data_included {
-            ret = ret | 0x80;
+            ret |= 0x80;
         }
 
         ret
@@ -46,6 +67,8 @@ impl fmt::Display for Flags {
         strbuf
             .append("- user_present_result", &self.user_present_result)
             .append("- user_verified_result", &self.user_verified_result)
+            .append("- backup_eligibility", &self.backup_eligibility)
+            .append("- backup_state", &self.backup_state)
             .append(
                 "- attested_credential_data_included",
                 &self.attested_credential_data_included,
@@ -54,3 +77,190 @@ impl fmt::Display for Flags {
         write!(f, "{}", strbuf.build())
     }
 }
+
+/// Attested Credential Data
+/// [https://www.w3.org/TR/webauthn/#sctn-attested-credential-data](https://www.w3.org/TR/webauthn/#sctn-attested-credential-data)
+#[derive(Debug, Clone, PartialEq)]
+pub struct AttestedCredentialData {
+    pub aaguid: Vec<u8>,
+    pub credential_id: Vec<u8>,
+    /// COSE_Key
+    pub credential_public_key: Value,
+}
+
+impl Default for AttestedCredentialData {
+    fn default() -> Self {
+        AttestedCredentialData {
+            aaguid: vec![0; 16],
+            credential_id: vec![],
+            credential_public_key: Value::Null,
+        }
+    }
+}
+
+impl AttestedCredentialData {
+    /// COSE algorithm of the credential public key
+    pub fn algorithm(&self) -> Result<i32> {
+        Ok(CoseKey::new(&self.credential_public_key)
+            .map_err(|e| anyhow!(e))?
+            .algorithm)
+    }
+
+    pub fn public_key(&self) -> Result<PublicKey> {
+        // PublicKey::new panics on a broken COSE_Key
+        CoseKey::new(&self.credential_public_key).map_err(|e| anyhow!(e))?;
+        Ok(PublicKey::new(&self.credential_public_key))
+    }
+}
+
+impl fmt::Display for AttestedCredentialData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut strbuf = StrBuf::new(42);
+        strbuf
+            .appenh("- aaguid", &self.aaguid)
+            .appenh("- credential_id", &self.credential_id);
+        if let Ok(public_key) = self.public_key() {
+            strbuf.append("- credential_public_key", &public_key);
+        }
+        write!(f, "{}", strbuf.build())
+    }
+}
+
+/// Authenticator Data
+/// - rpIdHash(32) || flags(1) || signCount(4) || [attestedCredentialData] || [extensions]
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct AuthenticatorData {
+    pub rpid_hash: Vec<u8>,
+    pub flags: Flags,
+    pub sign_count: u32,
+    /// present if flags.attested_credential_data_included
+    pub attested_credential_data: Option<AttestedCredentialData>,
+    /// extension identifier → authenticator extension output
+    /// (present if flags.extension_data_included)
+    pub extensions: BTreeMap<String, Value>,
+}
+
+impl AuthenticatorData {
+    pub fn parse(bytes: &[u8]) -> Result<AuthenticatorData> {
+        let mut reader = Reader { bytes, index: 0 };
+
+        let rpid_hash = reader.read(32)?.to_vec();
+        let flags = Flags::parse(reader.read(1)?[0])?;
+        let sign_count = u32::from_be_bytes(reader.read(4)?.try_into()?);
+
+        let attested_credential_data = if flags.attested_credential_data_included {
+            let aaguid = reader.read(16)?.to_vec();
+            let len = u16::from_be_bytes(reader.read(2)?.try_into()?);
+            let credential_id = reader.read(len as usize)?.to_vec();
+            let credential_public_key = reader.read_cbor()?;
+            Some(AttestedCredentialData {
+                aaguid,
+                credential_id,
+                credential_public_key,
+            })
+        } else {
+            None
+        };
+
+        let mut extensions = BTreeMap::new();
+        if flags.extension_data_included {
+            if let Value::Map(map) = reader.read_cbor()? {
+                for (key, val) in map {
+                    extensions.insert(util::cbor_value_to_str(&key).map_err(|e| anyhow!(e))?, val);
+                }
+            } else {
+                return Err(anyhow!("authenticator data: extensions is not a map"));
+            }
+        }
+
+        if reader.index != bytes.len() {
+            return Err(anyhow!("authenticator data: trailing bytes"));
+        }
+
+        Ok(AuthenticatorData {
+            rpid_hash,
+            flags,
+            sign_count,
+            attested_credential_data,
+            extensions,
+        })
+    }
+
+    /// The flags AT and ED are set from attested_credential_data and extensions.
+    pub fn serialize(&self) -> Result<Vec<u8>> {
+        if self.rpid_hash.len() != 32 {
+            return Err(anyhow!("authenticator data: rpIdHash must be 32 bytes"));
+        }
+
+        let mut flags = self.flags.clone();
+        flags.attested_credential_data_included = self.attested_credential_data.is_some();
+        flags.extension_data_included = !self.extensions.is_empty();
+
+        let mut ret = self.rpid_hash.to_vec();
+        ret.push(flags.as_u8());
+        ret.extend_from_slice(&self.sign_count.to_be_bytes());
+
+        if let Some(data) = &self.attested_credential_data {
+            if data.aaguid.len() != 16 {
+                return Err(anyhow!("authenticator data: aaguid must be 16 bytes"));
+            }
+            let len: u16 = data.credential_id.len().try_into()?;
+            ret.extend_from_slice(&data.aaguid);
+            ret.extend_from_slice(&len.to_be_bytes());
+            ret.extend_from_slice(&data.credential_id);
+            ret.append(&mut serde_cbor::to_vec(&data.credential_public_key)?);
+        }
+
+        if !self.extensions.is_empty() {
+            let map: BTreeMap<Value, Value> = self
+                .extensions
+                .iter()
+                .map(|(key, val)| (Value::Text(key.to_string()), val.clone()))
+                .collect();
+            ret.append(&mut serde_cbor::to_vec(&Value::Map(map))?);
+        }
+
+        Ok(ret)
+    }
+}
+
+impl fmt::Display for AuthenticatorData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut strbuf = StrBuf::new(42);
+        strbuf
+            .appenh("- rpid_hash", &self.rpid_hash)
+            .append("- sign_count", &self.sign_count)
+            .add(&format!("{}", &self.flags));
+        if let Some(data) = &self.attested_credential_data {
+            strbuf.add(&format!("{}", data));
+        }
+        for (key, val) in &self.extensions {
+            strbuf.append(&format!("- extension {}", key), &format!("{:?}", val));
+        }
+        write!(f, "{}", strbuf.build())
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    index: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.index + len > self.bytes.len() {
+            return Err(anyhow!("authenticator data: too short"));
+        }
+        let ret = &self.bytes[self.index..self.index + len];
+        self.index += len;
+        Ok(ret)
+    }
+
+    // one CBOR item
+    fn read_cbor(&mut self) -> Result<Value> {
+        let mut deserializer = serde_cbor::Deserializer::from_slice(&self.bytes[self.index..]);
+        let value: Value = serde::de::Deserialize::deserialize(&mut deserializer)?;
+        self.index += deserializer.byte_offset();
+        Ok(value)
+    }
+}
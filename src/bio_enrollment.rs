@@ -4,6 +4,7 @@ use crate::bio_enrollment_response;
 use crate::client_pin;
 use crate::client_pin_command::Permission;
 use crate::ctaphid;
+use crate::get_info;
 use crate::pintoken::PinToken;
 use crate::FidoKeyHid;
 
@@ -20,12 +21,20 @@ pub(crate) fn bio_enrollment(
     template_info: Option<TemplateInfo>,
     timeout_milliseconds: Option<u16>,
 ) -> Result<BioEnrollmentData> {
+    // resolved by bio_enrollment_init - otherwise from getInfo
+    let use_pre = match device.use_pre_bio_enrollment {
+        Some(use_pre) => use_pre,
+        None => {
+            get_info::get_info(device)?.is_prototype("bioEnroll", "userVerificationMgmtPreview")
+        }
+    };
+
     let send_payload = bio_enrollment_command::create_payload(
         pin_token,
         sub_command,
         template_info,
         timeout_milliseconds,
-        device.use_pre_bio_enrollment,
+        use_pre,
     );
 
     if device.enable_log {
@@ -42,23 +51,28 @@ pub(crate) fn bio_enrollment(
 }
 
 pub fn bio_enrollment_init(
-    device: &FidoKeyHid,
+    device: &mut FidoKeyHid,
     pin: Option<&str>,
 ) -> Result<([u8; 4], Option<PinToken>)> {
+    let info = get_info::get_info(device)?;
+    let use_pre = *device
+        .use_pre_bio_enrollment
+        .get_or_insert_with(|| info.is_prototype("bioEnroll", "userVerificationMgmtPreview"));
+
     // init
-    let cid = ctaphid::ctaphid_init(&device).map_err(Error::msg)?;
+    let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
 
     // pin token
     let pin_token = {
         if let Some(pin) = pin {
-            if device.use_pre_bio_enrollment {
+            if use_pre && info.option("pinUvAuthToken") != Some(true) {
                 Some(client_pin::get_pin_token(device, &cid, pin)?)
             } else {
                 Some(client_pin::get_pinuv_auth_token_with_permission(
                     device,
                     &cid,
                     pin,
-                    Permission::Be,
+                    &[Permission::Be],
                 )?)
             }
         } else {
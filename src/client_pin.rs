@@ -3,14 +3,21 @@ use anyhow::{anyhow, Error, Result};
 use crate::client_pin_command;
 use crate::client_pin_command::Permission;
 use crate::client_pin_command::SubCommand as PinCmd;
+use crate::client_pin_params::{
+    PinPolicyViolation, PinRetryError, UserVerification, UserVerificationMethod,
+};
 use crate::client_pin_response;
 use crate::cose;
 use crate::ctaphid;
 use crate::enc_aes256_cbc;
 use crate::enc_hmac_sha_256;
+use crate::get_info;
+use crate::get_info_params::Info;
+use crate::pin_provider::{PinContext, PinProvider, PinReason};
 use crate::pintoken::PinToken;
 use crate::ss::SharedSecret;
 use crate::FidoKeyHid;
+use zeroize::Zeroizing;
 
 pub fn get_authenticator_key_agreement(device: &FidoKeyHid, cid: &[u8]) -> Result<cose::CoseKey> {
     let send_payload =
@@ -22,6 +29,30 @@ pub fn get_authenticator_key_agreement(device: &FidoKeyHid, cid: &[u8]) -> Resul
     Ok(authenticator_key_agreement)
 }
 
+pub fn get_authenticator_key_agreement_with_protocol(
+    device: &FidoKeyHid,
+    cid: &[u8],
+    pin_uv_auth_protocol: u32,
+) -> Result<cose::CoseKey> {
+    let send_payload =
+        client_pin_command::create_payload_get_keyagreement_with_protocol(pin_uv_auth_protocol);
+    let response_cbor = ctaphid::ctaphid_cbor(device, cid, &send_payload).map_err(Error::msg)?;
+    let authenticator_key_agreement =
+        client_pin_response::parse_cbor_client_pin_get_keyagreement(&response_cbor)
+            .map_err(Error::msg)?;
+    Ok(authenticator_key_agreement)
+}
+
+// The first of pinUvAuthProtocols (in order of decreasing authenticator preference)
+// that this library supports. 1 if the authenticator does not report any.
+pub fn select_pin_uv_auth_protocol(info: &Info) -> u32 {
+    info.pin_uv_auth_protocols
+        .iter()
+        .find(|&&p| p == 1 || p == 2)
+        .copied()
+        .unwrap_or(1)
+}
+
 pub fn get_pin_token(device: &FidoKeyHid, cid: &[u8], pin: &str) -> Result<PinToken> {
     if !pin.is_empty() {
         let authenticator_key_agreement = get_authenticator_key_agreement(device, cid)?;
@@ -34,8 +65,7 @@ pub fn get_pin_token(device: &FidoKeyHid, cid: &[u8], pin: &str) -> Result<PinTo
             &pin_hash_enc,
         );
 
-        let response_cbor =
-            ctaphid::ctaphid_cbor(device, cid, &send_payload).map_err(Error::msg)?;
+        let response_cbor = send_pin(device, cid, &send_payload)?;
 
         // get pin_token (enc)
         let mut pin_token_enc =
@@ -57,7 +87,7 @@ pub fn get_pinuv_auth_token_with_permission(
     device: &FidoKeyHid,
     cid: &[u8],
     pin: &str,
-    permission: Permission,
+    permissions: &[Permission],
 ) -> Result<PinToken> {
     if !pin.is_empty() {
         let authenticator_key_agreement = get_authenticator_key_agreement(device, &cid)?;
@@ -72,10 +102,9 @@ pub fn get_pinuv_auth_token_with_permission(
             client_pin_command::create_payload_get_pin_uv_auth_token_using_pin_with_permissions(
                 &shared_secret.public_key,
                 &pin_hash_enc,
-                permission,
+                permissions,
             );
-        let response_cbor =
-            ctaphid::ctaphid_cbor(device, &cid, &send_payload).map_err(Error::msg)?;
+        let response_cbor = send_pin(device, cid, &send_payload)?;
 
         // get pin_token (enc)
         let mut pin_token_enc =
@@ -93,6 +122,231 @@ pub fn get_pinuv_auth_token_with_permission(
     }
 }
 
+pub fn get_pinuv_auth_token_using_uv_with_permission(
+    device: &FidoKeyHid,
+    cid: &[u8],
+    permissions: &[Permission],
+    rpid: &str,
+) -> Result<PinToken> {
+    let authenticator_key_agreement = get_authenticator_key_agreement(device, cid)?;
+    let shared_secret = SharedSecret::new(&authenticator_key_agreement).map_err(Error::msg)?;
+
+    let send_payload =
+        client_pin_command::create_payload_get_pin_uv_auth_token_using_uv_with_permissions(
+            &shared_secret.public_key,
+            permissions,
+            rpid,
+        );
+    let response_cbor = ctaphid::ctaphid_cbor(device, cid, &send_payload).map_err(Error::msg)?;
+
+    let mut pin_token_enc =
+        client_pin_response::parse_cbor_client_pin_get_pin_token(&response_cbor)
+            .map_err(Error::msg)?;
+    shared_secret
+        .decrypt_token(&mut pin_token_enc)
+        .map_err(Error::msg)
+}
+
+// UserVerification policy → UserVerificationMethod
+// - rk : Some(rk) for makeCredential, None for getAssertion
+pub(crate) fn select_user_verification_method(
+    info: &Info,
+    policy: UserVerification,
+    pin: Option<&str>,
+    rk: Option<bool>,
+) -> Result<UserVerificationMethod> {
+    let client_pin = info.option("clientPin") == Some(true);
+    let built_in_uv = info.option("uv") == Some(true);
+    let protected = client_pin || built_in_uv;
+
+    // the authenticator requires uv whatever the policy is
+    // - alwaysUv
+    // - makeCredential on a protected authenticator,
+    //   except non-discoverable credentials with makeCredUvNotRqd
+    let uv_required = info.option("alwaysUv") == Some(true)
+        || match rk {
+            Some(rk) => protected && (rk || info.option("makeCredUvNotRqd") != Some(true)),
+            None => false,
+        };
+
+    let uv = match policy {
+        UserVerification::Required => true,
+        UserVerification::Preferred => protected,
+        UserVerification::Discouraged => false,
+    };
+    if !uv && !uv_required {
+        return Ok(UserVerificationMethod::NotRequested);
+    }
+
+    if client_pin && matches!(pin, Some(pin) if !pin.is_empty()) {
+        Ok(UserVerificationMethod::PinToken)
+    } else if built_in_uv {
+        if info.option("pinUvAuthToken") == Some(true) {
+            Ok(UserVerificationMethod::UvToken)
+        } else {
+            Ok(UserVerificationMethod::UvOption)
+        }
+    } else if client_pin {
+        Err(anyhow!("User verification requires the PIN."))
+    } else {
+        Err(anyhow!(
+            "The authenticator does not support user verification."
+        ))
+    }
+}
+
+// The UserVerificationMethod of the pin / uv parameters of the API without a policy
+pub(crate) fn user_verification_method_of(
+    pin: Option<&str>,
+    uv: Option<bool>,
+) -> UserVerificationMethod {
+    match (pin, uv) {
+        (Some(pin), _) if !pin.is_empty() => UserVerificationMethod::PinToken,
+        (_, Some(true)) => UserVerificationMethod::UvOption,
+        _ => UserVerificationMethod::NotRequested,
+    }
+}
+
+// pinUvAuthToken of UserVerificationMethod::PinToken / UvToken
+pub(crate) fn get_user_verification_token(
+    device: &FidoKeyHid,
+    cid: &[u8],
+    method: UserVerificationMethod,
+    pin: Option<&str>,
+    permissions: &[Permission],
+    rpid: &str,
+) -> Result<Option<PinToken>> {
+    match method {
+        UserVerificationMethod::PinToken => {
+            Ok(Some(get_pin_token(device, cid, pin.unwrap_or_default())?))
+        }
+        UserVerificationMethod::UvToken => Ok(Some(get_pinuv_auth_token_using_uv_with_permission(
+            device,
+            cid,
+            permissions,
+            rpid,
+        )?)),
+        _ => Ok(None),
+    }
+}
+
+// Send a command carrying the PIN (pinHashEnc)
+// - with LibCfg::pin_retry_floor, the retries are checked first
+// - PIN errors are returned as PinRetryError
+fn send_pin(device: &FidoKeyHid, cid: &[u8], send_payload: &[u8]) -> Result<Vec<u8>> {
+    if let Some(floor) = device.pin_retry_floor {
+        let pin = get_retries(device, cid)?;
+        let retries = pin.retries.max(0) as u32;
+        if pin.power_cycle_state {
+            return Err(Error::new(PinRetryError::PowerCycleRequired { retries }));
+        }
+        if retries <= floor {
+            return Err(Error::new(PinRetryError::RetryFloorReached {
+                retries,
+                floor,
+            }));
+        }
+    }
+
+    match ctaphid::ctaphid_cbor(device, cid, send_payload) {
+        Ok(response_cbor) => Ok(response_cbor),
+        Err(err) if err.is_status(0x31) => {
+            let pin = get_retries(device, cid).ok();
+            Err(Error::new(PinRetryError::PinInvalid {
+                retries: pin.as_ref().map(|pin| pin.retries.max(0) as u32),
+                power_cycle_required: pin.is_some_and(|pin| pin.power_cycle_state),
+            }))
+        }
+        Err(err) if err.is_status(0x32) => Err(Error::new(PinRetryError::PinBlocked)),
+        Err(err) if err.is_status(0x34) => {
+            let retries = get_retries(device, cid)
+                .ok()
+                .map(|pin| pin.retries.max(0) as u32);
+            Err(Error::new(PinRetryError::PinAuthBlocked { retries }))
+        }
+        Err(err) => Err(Error::msg(err)),
+    }
+}
+
+pub fn get_retries(device: &FidoKeyHid, cid: &[u8]) -> Result<client_pin_response::Pin> {
+    let send_payload =
+        client_pin_command::create_payload(PinCmd::GetRetries).map_err(Error::msg)?;
+    let response_cbor = ctaphid::ctaphid_cbor(device, cid, &send_payload).map_err(Error::msg)?;
+    client_pin_response::parse_cbor_client_pin_get_retries(&response_cbor).map_err(Error::msg)
+}
+
+// Ask the PinProvider
+// - Verify : with the PIN retries left
+// - Set / Change : the new PIN is checked against the PIN policy
+pub(crate) fn provide_pin(
+    device: &FidoKeyHid,
+    info: &Info,
+    provider: &dyn PinProvider,
+    reason: PinReason,
+    current_pin: Option<&str>,
+) -> Result<Zeroizing<String>> {
+    let retries = if reason == PinReason::Verify {
+        let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+        Some(get_retries(device, &cid)?.retries.max(0) as u32)
+    } else {
+        None
+    };
+    let context = PinContext {
+        reason,
+        retries,
+        min_pin_length: if info.min_pin_length > 0 {
+            info.min_pin_length
+        } else {
+            DEFAULT_MIN_PIN_LENGTH
+        },
+    };
+
+    let pin = provider.provide_pin(&context)?;
+    if reason != PinReason::Verify {
+        check_pin_policy(info, &pin, current_pin)?;
+    }
+    Ok(pin)
+}
+
+// Whether the policy cannot be met without the PIN
+pub(crate) fn is_pin_required(
+    info: &Info,
+    policy: UserVerification,
+    rk: Option<bool>,
+) -> Result<bool> {
+    match select_user_verification_method(info, policy, None, rk) {
+        Ok(_) => Ok(false),
+        // user verification requires the PIN
+        Err(_) if info.option("clientPin") == Some(true) => Ok(true),
+        Err(err) => Err(err),
+    }
+}
+
+// The PIN for user verification - asked for only if the policy cannot be met without it,
+// e.g. built-in user verification is used instead if the authenticator has one.
+// If forcePINChange is set, the PIN is changed first and the new PIN is returned.
+pub(crate) fn provide_pin_for_user_verification(
+    device: &FidoKeyHid,
+    provider: &dyn PinProvider,
+    policy: UserVerification,
+    rk: Option<bool>,
+) -> Result<Option<Zeroizing<String>>> {
+    let info = get_info::get_info(device)?;
+    if !is_pin_required(&info, policy, rk)? {
+        return Ok(None);
+    }
+
+    let pin = provide_pin(device, &info, provider, PinReason::Verify, None)?;
+    if !info.force_pin_change {
+        return Ok(Some(pin));
+    }
+
+    let new_pin = provide_pin(device, &info, provider, PinReason::Change, Some(&pin))?;
+    let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+    change_pin(device, &cid, &pin, &new_pin)?;
+    Ok(Some(new_pin))
+}
+
 pub fn set_pin(device: &FidoKeyHid, cid: &[u8], pin: &str) -> Result<()> {
     if pin.is_empty() {
         return Err(anyhow!("new pin not set"));
@@ -155,7 +409,46 @@ fn create_pin_auth_for_change_pin(
     Ok(pin_auth)
 }
 
-fn padding_pin_64(pin: &str) -> Result<Vec<u8>> {
+// 6.5.1. PIN Composition Requirements
+// - minimum length : minPINLength Unicode code points (4 if absent)
+// - maximum length : 63 bytes in UTF-8 representation
+const DEFAULT_MIN_PIN_LENGTH: u32 = 4;
+const MAX_PIN_BYTES: usize = 63;
+
+pub fn check_pin_policy(
+    info: &Info,
+    new_pin: &str,
+    current_pin: Option<&str>,
+) -> Result<(), PinPolicyViolation> {
+    let min_pin_length = if info.min_pin_length > 0 {
+        info.min_pin_length
+    } else {
+        DEFAULT_MIN_PIN_LENGTH
+    };
+
+    let length = new_pin.chars().count();
+    if length < min_pin_length as usize {
+        return Err(PinPolicyViolation::TooShort {
+            min_pin_length,
+            length,
+        });
+    }
+
+    if new_pin.len() > MAX_PIN_BYTES {
+        return Err(PinPolicyViolation::TooLong {
+            max_pin_bytes: MAX_PIN_BYTES,
+            length: new_pin.len(),
+        });
+    }
+
+    if info.force_pin_change && current_pin == Some(new_pin) {
+        return Err(PinPolicyViolation::SameAsCurrentPin);
+    }
+
+    Ok(())
+}
+
+fn padding_pin_64(pin: &str) -> Result<Zeroizing<Vec<u8>>> {
     // 5.5.5. Setting a New PIN
     // 5.5.6. Changing existing PIN
     // During encryption,
@@ -163,7 +456,7 @@ fn padding_pin_64(pin: &str) -> Result<Vec<u8>> {
     // This is to prevent leak of PIN length while communicating to the authenticator.
     // There is no PKCS #7 padding used in this scheme.
 
-    let mut bpin64: Vec<u8> = vec![0; 64];
+    let mut bpin64 = Zeroizing::new(vec![0u8; 64]);
     let pintmp = pin.as_bytes();
 
     for (i, val) in pintmp.iter().enumerate() {
@@ -213,7 +506,7 @@ pub fn change_pin(device: &FidoKeyHid, cid: &[u8], current_pin: &str, new_pin: &
         &current_pin_hash_enc,
     );
 
-    ctaphid::ctaphid_cbor(device, cid, &send_payload).map_err(Error::msg)?;
+    send_pin(device, cid, &send_payload)?;
 
     Ok(())
 }
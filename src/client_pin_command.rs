@@ -16,6 +16,7 @@ pub enum SubCommand {
 }
 
 #[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum Permission {
     Mc = 0x01,
     Ga = 0x02,
@@ -23,6 +24,8 @@ pub enum Permission {
     Be = 0x08,
     Lbw = 0x10,
     Acfg = 0x20,
+    // persistent credential management read-only (CTAP 2.2)
+    Pcmr = 0x40,
 }
 
 fn create_payload_get_uv_retries() -> Vec<u8> {
@@ -40,8 +43,15 @@ fn create_payload_get_retries() -> Vec<u8> {
 }
 
 fn create_payload_get_keyagreement() -> Vec<u8> {
+    create_payload_get_keyagreement_with_protocol(1)
+}
+
+pub fn create_payload_get_keyagreement_with_protocol(pin_uv_auth_protocol: u32) -> Vec<u8> {
     let mut map = BTreeMap::new();
-    insert_pin_protocol(&mut map);
+    map.insert(
+        Value::Integer(0x01),
+        Value::Integer(pin_uv_auth_protocol as i128),
+    );
     insert_sub_command(&mut map, SubCommand::GetKeyAgreement);
     to_payload(map)
 }
@@ -88,7 +98,7 @@ pub fn create_payload_change_pin(
 pub fn create_payload_get_pin_uv_auth_token_using_pin_with_permissions(
     key_agreement: &cose::CoseKey,
     pin_hash_enc: &[u8],
-    permission: Permission,
+    permissions: &[Permission],
 ) -> Vec<u8> {
     let mut map = BTreeMap::new();
     insert_pin_protocol(&mut map);
@@ -102,27 +112,25 @@ pub fn create_payload_get_pin_uv_auth_token_using_pin_with_permissions(
     let value = Value::Bytes(pin_hash_enc.to_vec());
     map.insert(Value::Integer(0x06), value);
 
-    // permission(0x09) - Unsigned Integer
-    let value = Value::Integer(permission as i128);
-    map.insert(Value::Integer(0x09), value);
+    insert_permissions(&mut map, permissions);
 
     to_payload(map)
 }
 
-/* TODO WIP
 pub fn create_payload_get_pin_uv_auth_token_using_uv_with_permissions(
     key_agreement: &cose::CoseKey,
-    permission: Permission,
+    permissions: &[Permission],
     rpid: &str,
 ) -> Vec<u8> {
     let mut map = BTreeMap::new();
     insert_pin_protocol(&mut map);
-    insert_sub_command(&mut map, SubCommand::GetPinUvAuthTokenUsingUvWithPermissions);
+    insert_sub_command(
+        &mut map,
+        SubCommand::GetPinUvAuthTokenUsingUvWithPermissions,
+    );
     insert_key_agreement(&mut map, key_agreement);
 
-    // permission(0x09) - Unsigned Integer
-    let value = Value::Integer(permission as i128);
-    map.insert(Value::Integer(0x09), value);
+    insert_permissions(&mut map, permissions);
 
     // rpid(0x0A) - String
     let value = Value::Text(rpid.to_string());
@@ -130,7 +138,14 @@ pub fn create_payload_get_pin_uv_auth_token_using_uv_with_permissions(
 
     to_payload(map)
 }
- */
+
+// permissions(0x09) - Unsigned Integer (bitfield)
+fn insert_permissions(map: &mut BTreeMap<Value, Value>, permissions: &[Permission]) {
+    let value = permissions
+        .iter()
+        .fold(0, |bits, &permission| bits | permission as i128);
+    map.insert(Value::Integer(0x09), Value::Integer(value));
+}
 
 // create payload
 fn to_payload(map: BTreeMap<Value, Value>) -> Vec<u8> {
@@ -0,0 +1,170 @@
+/*!
+client_pin API parameters
+*/
+
+use std::fmt;
+
+/// PIN policy violation detected before the PIN is sent to the authenticator
+#[derive(Debug, Clone, PartialEq)]
+pub enum PinPolicyViolation {
+    /// The number of Unicode code points is less than minPINLength
+    TooShort { min_pin_length: u32, length: usize },
+    /// The UTF-8 representation is longer than 63 bytes
+    TooLong { max_pin_bytes: usize, length: usize },
+    /// forcePINChange is set and the new PIN is the same as the current PIN
+    SameAsCurrentPin,
+}
+
+impl fmt::Display for PinPolicyViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PinPolicyViolation::TooShort {
+                min_pin_length,
+                length,
+            } => write!(
+                f,
+                "PIN policy violation: PIN is too short. ({} < minPINLength:{})",
+                length, min_pin_length
+            ),
+            PinPolicyViolation::TooLong {
+                max_pin_bytes,
+                length,
+            } => write!(
+                f,
+                "PIN policy violation: PIN is too long. ({} bytes > {} bytes)",
+                length, max_pin_bytes
+            ),
+            PinPolicyViolation::SameAsCurrentPin => write!(
+                f,
+                "PIN policy violation: forcePINChange is set, the new PIN must be different from the current PIN."
+            ),
+        }
+    }
+}
+
+impl std::error::Error for PinPolicyViolation {}
+
+/// User verification policy of makeCredential / getAssertion
+/// [https://www.w3.org/TR/webauthn/#enumdef-userverificationrequirement](https://www.w3.org/TR/webauthn/#enumdef-userverificationrequirement)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UserVerification {
+    /// fails if the authenticator cannot verify the user
+    Required,
+    /// verify the user if the authenticator is protected by a PIN or built-in uv
+    Preferred,
+    /// do not verify the user unless the authenticator requires it (alwaysUv, ...)
+    Discouraged,
+}
+
+/// How the user was verified
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum UserVerificationMethod {
+    /// neither pinUvAuthParam nor the uv option was sent
+    #[default]
+    NotRequested,
+    /// pinUvAuthParam with a pinUvAuthToken obtained with the PIN
+    PinToken,
+    /// pinUvAuthParam with a pinUvAuthToken obtained with built-in uv
+    UvToken,
+    /// the uv option (built-in uv of an authenticator without pinUvAuthToken)
+    UvOption,
+}
+
+impl fmt::Display for UserVerificationMethod {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            UserVerificationMethod::NotRequested => "not requested",
+            UserVerificationMethod::PinToken => "pinUvAuthToken (PIN)",
+            UserVerificationMethod::UvToken => "pinUvAuthToken (built-in uv)",
+            UserVerificationMethod::UvOption => "uv option (built-in uv)",
+        };
+        write!(f, "{}", message)
+    }
+}
+
+/// PIN retry guard / PIN errors with the retry state of the authenticator
+#[derive(Debug, Clone, PartialEq)]
+pub enum PinRetryError {
+    /// The guard did not try the PIN: retries are at or below the floor (LibCfg::pin_retry_floor)
+    RetryFloorReached { retries: u32, floor: u32 },
+    /// The guard did not try the PIN: powerCycleState is set, the authenticator must be power cycled
+    PowerCycleRequired { retries: u32 },
+    /// CTAP2_ERR_PIN_INVALID
+    PinInvalid {
+        retries: Option<u32>,
+        power_cycle_required: bool,
+    },
+    /// CTAP2_ERR_PIN_AUTH_BLOCKED - too many consecutive mismatches, power cycle required
+    PinAuthBlocked { retries: Option<u32> },
+    /// CTAP2_ERR_PIN_BLOCKED - no retries left, the authenticator must be reset
+    PinBlocked,
+}
+
+impl PinRetryError {
+    /// PIN retries left (if known)
+    pub fn retries(&self) -> Option<u32> {
+        match self {
+            PinRetryError::RetryFloorReached { retries, .. } => Some(*retries),
+            PinRetryError::PowerCycleRequired { retries } => Some(*retries),
+            PinRetryError::PinInvalid { retries, .. } => *retries,
+            PinRetryError::PinAuthBlocked { retries } => *retries,
+            PinRetryError::PinBlocked => Some(0),
+        }
+    }
+
+    pub fn power_cycle_required(&self) -> bool {
+        match self {
+            PinRetryError::PowerCycleRequired { .. } => true,
+            PinRetryError::PinInvalid {
+                power_cycle_required,
+                ..
+            } => *power_cycle_required,
+            PinRetryError::PinAuthBlocked { .. } => true,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for PinRetryError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let retries = |retries: &Option<u32>| match retries {
+            Some(retries) => format!("{}", retries),
+            None => "unknown".to_string(),
+        };
+        match self {
+            PinRetryError::RetryFloorReached { retries, floor } => write!(
+                f,
+                "PIN was not sent: {} retries left (floor:{}).",
+                retries, floor
+            ),
+            PinRetryError::PowerCycleRequired { retries } => write!(
+                f,
+                "PIN was not sent: the authenticator must be power cycled. ({} retries left)",
+                retries
+            ),
+            PinRetryError::PinInvalid {
+                retries: r,
+                power_cycle_required,
+            } => write!(
+                f,
+                "PIN is invalid. ({} retries left{})",
+                retries(r),
+                if *power_cycle_required {
+                    ", power cycle required"
+                } else {
+                    ""
+                }
+            ),
+            PinRetryError::PinAuthBlocked { retries: r } => write!(
+                f,
+                "PIN is blocked until the authenticator is power cycled. ({} retries left)",
+                retries(r)
+            ),
+            PinRetryError::PinBlocked => {
+                write!(f, "PIN is blocked. The authenticator must be reset.")
+            }
+        }
+    }
+}
+
+impl std::error::Error for PinRetryError {}
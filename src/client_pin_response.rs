@@ -5,6 +5,7 @@ use serde_cbor::Value;
 #[derive(Default)]
 pub struct Pin {
     pub retries: i32,
+    pub power_cycle_state: bool,
     pub uv_retries: i32,
 }
 
@@ -49,6 +50,7 @@ pub fn parse_cbor_client_pin_get_retries(bytes: &[u8]) -> Result<Pin, String> {
             if let Value::Integer(member) = key {
                 match member {
                     3 => pin.retries = util::cbor_value_to_num(val)?,
+                    4 => pin.power_cycle_state = util::cbor_value_to_bool(val)?,
                     5 => pin.uv_retries = util::cbor_value_to_num(val)?,
                     _ => println!("- anything error"),
                 }
@@ -19,6 +19,15 @@ impl fmt::Display for CoseKey {
         strbuf
             .append("- key_type", &self.key_type)
             .append("- algorithm", &self.algorithm);
+        if self.key_type == 3 {
+            if let Some(Value::Bytes(bytes)) = self.parameters.get(&-1) {
+                strbuf.appenh("- n", bytes);
+            }
+            if let Some(Value::Bytes(bytes)) = self.parameters.get(&-2) {
+                strbuf.appenh("- e", bytes);
+            }
+            return write!(f, "{}", strbuf.build());
+        }
         if let Some(Value::Integer(intval)) = self.parameters.get(&-1) {
             strbuf.append("- crv", &intval);
         }
@@ -49,6 +58,7 @@ impl CoseKey {
                             // 1: kty
                             //      1: OKP (Octet Key Pair) → need x
                             //      2: EC2 (Double Coordinate Curves) → need x&y
+                            //      3: RSA → need n&e
                             cose.key_type = util::cbor_value_to_num(val)?;
                         }
                         // 2: kid
@@ -59,27 +69,26 @@ impl CoseKey {
                             //      -25: ECDH-ES + HKDF-256
                             //      -35: ES384
                             //      -36: ES512
+                            //     -257: RS256
                             cose.algorithm = util::cbor_value_to_num(val)?;
                         }
                         // 4: key_ops
                         // 5: Base IV
-                        -1 => {
+                        -3..=-1 => {
                             // Table 22: Elliptic Curves
-                            // -1: Curves
+                            // -1: Curves (EC2/OKP) / n (RSA)
                             //      1: P-256(EC2)
+                            //      2: P-384(EC2)
+                            //      3: P-521(EC2)
                             //      6: Ed25519(OKP)
+                            // -2: x (EC2/OKP) / e (RSA)
+                            // -3: y (EC2)
                             //println!("member = {:?} , val = {:?}",member,val);
-                            cose.parameters.insert(
-                                NumCast::from(*member).unwrap(),
-                                Value::Integer(util::cbor_value_to_num(val)?),
-                            );
-                        }
-                        -2 | -3 => {
-                            //println!("member = {:?} , val = {:?}",member,val);
-                            cose.parameters.insert(
-                                NumCast::from(*member).unwrap(),
-                                Value::Bytes(util::cbor_value_to_vec_u8(val).unwrap()),
-                            );
+                            let val = match val {
+                                Value::Bytes(_) => Value::Bytes(util::cbor_value_to_vec_u8(val)?),
+                                _ => Value::Integer(util::cbor_value_to_num(val)?),
+                            };
+                            cose.parameters.insert(NumCast::from(*member).unwrap(), val);
                         }
                         _ => {}
                     }
@@ -93,44 +102,83 @@ impl CoseKey {
         let mut map = BTreeMap::new();
         map.insert(Value::Integer(1), Value::Integer(self.key_type.into()));
         map.insert(Value::Integer(3), Value::Integer(self.algorithm.into()));
-        map.insert(
-            Value::Integer(-1),
-            self.parameters.get(&-1).unwrap().clone(),
-        );
-        map.insert(
-            Value::Integer(-2),
-            self.parameters.get(&-2).unwrap().clone(),
-        );
-        map.insert(
-            Value::Integer(-3),
-            self.parameters.get(&-3).unwrap().clone(),
-        );
+        for (member, val) in &self.parameters {
+            map.insert(Value::Integer((*member).into()), val.clone());
+        }
         Ok(Value::Map(map))
     }
 
     pub fn to_public_key_der(&self) -> Vec<u8> {
-        if self.key_type == 2 {
-            // kty == 2: EC2 → need x&y
+        match self.key_type {
+            2 => {
+                // kty == 2: EC2 → need x&y
 
-            // tag:0x04(OCTET STRING)
-            let mut pub_key = vec![0x04];
+                // tag:0x04(OCTET STRING)
+                let mut pub_key = vec![0x04];
 
-            // 2.add X
-            if let Some(Value::Bytes(bytes)) = self.parameters.get(&-2) {
-                pub_key.append(&mut bytes.to_vec());
+                // 2.add X
+                if let Some(Value::Bytes(bytes)) = self.parameters.get(&-2) {
+                    pub_key.append(&mut bytes.to_vec());
+                }
+                // 3.add Y
+                if let Some(Value::Bytes(bytes)) = self.parameters.get(&-3) {
+                    pub_key.append(&mut bytes.to_vec());
+                }
+
+                pub_key
+            }
+            1 => {
+                // kty == 1: OKP → need x
+                match self.parameters.get(&-2) {
+                    Some(Value::Bytes(bytes)) => bytes.to_vec(),
+                    _ => vec![],
+                }
             }
-            // 3.add Y
-            if let Some(Value::Bytes(bytes)) = self.parameters.get(&-3) {
-                pub_key.append(&mut bytes.to_vec());
+            3 => {
+                // kty == 3: RSA → RSAPublicKey ::= SEQUENCE { modulus INTEGER, publicExponent INTEGER }
+                match (self.parameters.get(&-1), self.parameters.get(&-2)) {
+                    (Some(Value::Bytes(n)), Some(Value::Bytes(e))) => {
+                        let mut seq = util::der_unsigned_integer(n);
+                        seq.append(&mut util::der_unsigned_integer(e));
+                        util::der_tlv(0x30, &seq)
+                    }
+                    _ => vec![],
+                }
             }
+            _ => vec![],
+        }
+    }
 
-            pub_key
-        } else {
-            // kty == 1: OKP → need x&y
+    // SubjectPublicKeyInfo ::= SEQUENCE { algorithm AlgorithmIdentifier, subjectPublicKey BIT STRING }
+    // - empty if the key type or curve is not known
+    pub fn to_subject_public_key_info(&self) -> Vec<u8> {
+        let algorithm_identifier = match (self.key_type, self.parameters.get(&-1)) {
+            // id-ecPublicKey + namedCurve (secp256r1 / secp384r1 / secp521r1)
+            (2, Some(Value::Integer(1))) => util::der_ec_algorithm("2a8648ce3d030107"),
+            (2, Some(Value::Integer(2))) => util::der_ec_algorithm("2b81040022"),
+            (2, Some(Value::Integer(3))) => util::der_ec_algorithm("2b81040023"),
+            // id-Ed25519
+            (1, Some(Value::Integer(6))) => util::der_tlv(0x30, &util::der_oid("2b6570")),
+            // rsaEncryption + NULL
+            (3, _) => {
+                let mut seq = util::der_oid("2a864886f70d010101");
+                seq.append(&mut vec![0x05, 0x00]);
+                util::der_tlv(0x30, &seq)
+            }
+            _ => return vec![],
+        };
 
-            // TODO
-            // ???
-            vec![]
+        let public_key = self.to_public_key_der();
+        if public_key.is_empty() {
+            return vec![];
         }
+
+        // BIT STRING : 0 unused bits || public key
+        let mut bit_string = vec![0x00];
+        bit_string.append(&mut public_key.to_vec());
+
+        let mut spki = algorithm_identifier;
+        spki.append(&mut util::der_tlv(0x03, &bit_string));
+        util::der_tlv(0x30, &spki)
     }
 }
@@ -1,62 +1,350 @@
 use crate::client_pin;
 use crate::client_pin_command::Permission;
 use crate::credential_management_command;
+use crate::credential_management_command::SubCommand;
 use crate::credential_management_params;
+use crate::credential_management_params::{
+    Credential, PersistentPinUvAuthToken, Rp, RpCredentials,
+};
 use crate::credential_management_response;
 use crate::ctaphid;
+use crate::ctaphid::CtapHidError;
+use crate::get_info;
+use crate::get_info_params::Info;
+use crate::paging::Paging;
+use crate::pintoken::PinToken;
 use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
 use crate::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
 use crate::FidoKeyHid;
 
 #[allow(unused_imports)]
 use crate::util;
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
 
 pub(crate) fn credential_management(
-    device: &FidoKeyHid,
+    device: &mut FidoKeyHid,
     pin: Option<&str>,
     sub_command: credential_management_command::SubCommand,
     rpid_hash: Option<Vec<u8>>,
     pkcd: Option<PublicKeyCredentialDescriptor>,
     pkcue: Option<PublicKeyCredentialUserEntity>,
 ) -> Result<credential_management_params::CredentialManagementData> {
-    let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+    let session = Session::open(device, pin)?;
+    session
+        .send(device, sub_command, rpid_hash, pkcd, pkcue)
+        .map_err(Error::msg)
+}
+
+// One channel and pinUvAuthToken shared by the subcommands of an enumeration
+pub(crate) struct Session {
+    cid: [u8; 4],
+    pin_token: Option<PinToken>,
+    use_pre: bool,
+}
 
-    // pin token
-    let pin_token = {
-        if let Some(pin) = pin {
-            if device.use_pre_credential_management {
-                Some(client_pin::get_pin_token(device, &cid, pin)?)
+impl Session {
+    pub(crate) fn open(device: &mut FidoKeyHid, pin: Option<&str>) -> Result<Session> {
+        let info = get_info::get_info(device)?;
+        let use_pre = *device
+            .use_pre_credential_management
+            .get_or_insert_with(|| info.is_prototype("credMgmt", "credentialMgmtPreview"));
+
+        let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+
+        // pin token
+        let pin_token = {
+            if let Some(pin) = pin {
+                if use_pre && info.option("pinUvAuthToken") != Some(true) {
+                    Some(client_pin::get_pin_token(device, &cid, pin)?)
+                } else {
+                    Some(client_pin::get_pinuv_auth_token_with_permission(
+                        device,
+                        &cid,
+                        pin,
+                        &[Permission::Cm],
+                    )?)
+                }
             } else {
-                Some(client_pin::get_pinuv_auth_token_with_permission(
-                    device,
-                    &cid,
-                    pin,
-                    Permission::Cm,
-                )?)
+                None
             }
-        } else {
-            None
+        };
+
+        Ok(Session {
+            cid,
+            pin_token,
+            use_pre,
+        })
+    }
+
+    // Session authorized by a persistentPinUvAuthToken (pcmr)
+    pub(crate) fn open_with_token(
+        device: &mut FidoKeyHid,
+        token: &PersistentPinUvAuthToken,
+    ) -> Result<Session> {
+        let info = get_info::get_info(device)?;
+        check_per_cred_mgmt_ro(&info)?;
+        let use_pre = *device
+            .use_pre_credential_management
+            .get_or_insert_with(|| info.is_prototype("credMgmt", "credentialMgmtPreview"));
+
+        let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+
+        Ok(Session {
+            cid,
+            pin_token: Some(PinToken::new(token.key())),
+            use_pre,
+        })
+    }
+
+    pub(crate) fn send(
+        &self,
+        device: &FidoKeyHid,
+        sub_command: SubCommand,
+        rpid_hash: Option<Vec<u8>>,
+        pkcd: Option<PublicKeyCredentialDescriptor>,
+        pkcue: Option<PublicKeyCredentialUserEntity>,
+    ) -> Result<credential_management_params::CredentialManagementData, CtapHidError> {
+        // the get-next subcommands have no pinUvAuthParam
+        let pin_token = match sub_command {
+            SubCommand::EnumerateRPsGetNextRp
+            | SubCommand::EnumerateCredentialsGetNextCredential => None,
+            _ => self.pin_token.as_ref(),
+        };
+
+        let send_payload = credential_management_command::create_payload(
+            pin_token,
+            sub_command,
+            rpid_hash,
+            pkcd,
+            pkcue,
+            self.use_pre,
+        );
+
+        if device.enable_log {
+            println!("send(cbor) = {}", util::to_hex_str(&send_payload));
+        }
+
+        let response_cbor = ctaphid::ctaphid_cbor(device, &self.cid, &send_payload)?;
+        if device.enable_log {
+            println!("response(cbor) = {}", util::to_hex_str(&response_cbor));
+        }
+
+        Ok(credential_management_response::parse_cbor(&response_cbor)?)
+    }
+
+    pub(crate) fn enumerate_rps(self, device: FidoKeyHid) -> Result<RpIter> {
+        enumerate_rps(Box::new(SessionEnumerator {
+            device,
+            session: self,
+        }))
+    }
+
+    pub(crate) fn enumerate_credentials(
+        self,
+        device: FidoKeyHid,
+        rp: &Rp,
+    ) -> Result<CredentialIter> {
+        enumerate_credentials(
+            Box::new(SessionEnumerator {
+                device,
+                session: self,
+            }),
+            rp,
+        )
+    }
+}
+
+// The subcommands of an enumeration
+// - sent to the authenticator with the session (canned responses in the tests)
+pub(crate) trait Enumerator: Send {
+    fn send(
+        &mut self,
+        sub_command: SubCommand,
+        rpid_hash: Option<Vec<u8>>,
+    ) -> Result<credential_management_params::CredentialManagementData, CtapHidError>;
+}
+
+struct SessionEnumerator {
+    device: FidoKeyHid,
+    session: Session,
+}
+
+impl Enumerator for SessionEnumerator {
+    fn send(
+        &mut self,
+        sub_command: SubCommand,
+        rpid_hash: Option<Vec<u8>>,
+    ) -> Result<credential_management_params::CredentialManagementData, CtapHidError> {
+        self.session
+            .send(&self.device, sub_command, rpid_hash, None, None)
+    }
+}
+
+// *Begin subcommand - CTAP2_ERR_NO_CREDENTIALS means there is nothing to enumerate
+fn begin(
+    enumerator: &mut dyn Enumerator,
+    sub_command: SubCommand,
+    rpid_hash: Option<Vec<u8>>,
+) -> Result<Option<credential_management_params::CredentialManagementData>> {
+    match enumerator.send(sub_command, rpid_hash) {
+        Ok(data) => Ok(Some(data)),
+        Err(err) if err.is_status(0x2E) => Ok(None),
+        Err(err) => Err(Error::msg(err)),
+    }
+}
+
+fn next_rp(enumerator: &mut dyn Enumerator) -> Result<Rp> {
+    let data = enumerator
+        .send(SubCommand::EnumerateRPsGetNextRp, None)
+        .map_err(Error::msg)?;
+    Ok(Rp::new(&data))
+}
+
+fn next_credential(enumerator: &mut dyn Enumerator, rp: &Rp) -> Result<Credential> {
+    let data = enumerator
+        .send(SubCommand::EnumerateCredentialsGetNextCredential, None)
+        .map_err(Error::msg)?;
+    Ok(Credential::new(&data).with_rp(rp))
+}
+
+fn credentials(enumerator: &mut dyn Enumerator, rp: &Rp) -> Result<Vec<Credential>> {
+    let first = begin(
+        enumerator,
+        SubCommand::EnumerateCredentialsBegin,
+        Some(rp.rpid_hash.to_vec()),
+    )?;
+
+    let mut credentials = vec![];
+    if let Some(data) = first.filter(|data| data.total_credentials > 0) {
+        credentials.push(Credential::new(&data).with_rp(rp));
+        for _ in 1..data.total_credentials {
+            credentials.push(next_credential(enumerator, rp)?);
         }
+    }
+    Ok(credentials)
+}
+
+pub(crate) fn enumerate_rps(mut enumerator: Box<dyn Enumerator>) -> Result<RpIter> {
+    let first = begin(enumerator.as_mut(), SubCommand::EnumerateRPsBegin, None)?;
+    let paging = match first {
+        Some(data) if data.total_rps > 0 => Paging::new(Some(Rp::new(&data)), data.total_rps),
+        _ => Paging::new(None, 0),
+    };
+
+    Ok(RpIter { enumerator, paging })
+}
+
+pub(crate) fn enumerate_credentials(
+    mut enumerator: Box<dyn Enumerator>,
+    rp: &Rp,
+) -> Result<CredentialIter> {
+    let first = begin(
+        enumerator.as_mut(),
+        SubCommand::EnumerateCredentialsBegin,
+        Some(rp.rpid_hash.to_vec()),
+    )?;
+    let paging = match first {
+        Some(data) if data.total_credentials > 0 => Paging::new(
+            Some(Credential::new(&data).with_rp(rp)),
+            data.total_credentials,
+        ),
+        _ => Paging::new(None, 0),
     };
 
-    let send_payload = credential_management_command::create_payload(
-        pin_token,
-        sub_command,
-        rpid_hash,
-        pkcd,
-        pkcue,
-        device.use_pre_credential_management,
-    );
+    Ok(CredentialIter {
+        enumerator,
+        rp: rp.clone(),
+        paging,
+    })
+}
+
+/// RPs fetched one by one with enumerateRPsGetNextRP.
+/// The session and pinUvAuthToken are held until the iterator is dropped.
+pub struct RpIter {
+    enumerator: Box<dyn Enumerator>,
+    paging: Paging<Rp>,
+}
+
+impl RpIter {
+    /// totalRPs of enumerateRPsBegin
+    pub fn total_rps(&self) -> u32 {
+        self.paging.total()
+    }
 
-    if device.enable_log {
-        println!("send(cbor) = {}", util::to_hex_str(&send_payload));
+    // enumerate the credentials of every RP with the session of this iterator
+    pub(crate) fn into_tree(mut self) -> Result<Vec<RpCredentials>> {
+        let rps = self.by_ref().collect::<Result<Vec<Rp>>>()?;
+        rps.into_iter()
+            .map(|rp| {
+                let credentials = credentials(self.enumerator.as_mut(), &rp)?;
+                Ok(RpCredentials { rp, credentials })
+            })
+            .collect()
     }
+}
+
+impl Iterator for RpIter {
+    type Item = Result<Rp>;
 
-    let response_cbor = ctaphid::ctaphid_cbor(device, &cid, &send_payload).map_err(Error::msg)?;
-    if device.enable_log {
-        println!("response(cbor) = {}", util::to_hex_str(&response_cbor));
+    fn next(&mut self) -> Option<Self::Item> {
+        let enumerator = self.enumerator.as_mut();
+        self.paging.next_with(|| next_rp(enumerator))
     }
 
-    Ok(credential_management_response::parse_cbor(&response_cbor).map_err(Error::msg)?)
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.paging.remaining() as usize))
+    }
+}
+
+/// Credentials of an RP fetched one by one with enumerateCredentialsGetNextCredential.
+/// The session and pinUvAuthToken are held until the iterator is dropped.
+pub struct CredentialIter {
+    enumerator: Box<dyn Enumerator>,
+    rp: Rp,
+    paging: Paging<Credential>,
+}
+
+impl CredentialIter {
+    /// totalCredentials of enumerateCredentialsBegin
+    pub fn total_credentials(&self) -> u32 {
+        self.paging.total()
+    }
+}
+
+impl Iterator for CredentialIter {
+    type Item = Result<Credential>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (enumerator, rp) = (self.enumerator.as_mut(), &self.rp);
+        self.paging.next_with(|| next_credential(enumerator, rp))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.paging.remaining() as usize))
+    }
+}
+
+// getPinUvAuthTokenUsingPinWithPermissions with the pcmr permission only
+pub(crate) fn get_persistent_token(
+    device: &FidoKeyHid,
+    pin: &str,
+) -> Result<PersistentPinUvAuthToken> {
+    let info = get_info::get_info(device)?;
+    check_per_cred_mgmt_ro(&info)?;
+
+    let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+    let pin_token =
+        client_pin::get_pinuv_auth_token_with_permission(device, &cid, pin, &[Permission::Pcmr])?;
+
+    Ok(PersistentPinUvAuthToken { pin_token })
+}
+
+fn check_per_cred_mgmt_ro(info: &Info) -> Result<()> {
+    if info.option("perCredMgmtRO") == Some(true) {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "perCredMgmtRO is not supported. (persistent credential management read-only token)"
+        ))
+    }
 }
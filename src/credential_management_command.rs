@@ -57,7 +57,7 @@ fn parse_test(cbor:Value){
 */
 
 pub fn create_payload(
-    pin_token: Option<pintoken::PinToken>,
+    pin_token: Option<&pintoken::PinToken>,
     sub_command: SubCommand,
     rpid_hash: Option<Vec<u8>>,
     pkcd: Option<PublicKeyCredentialDescriptor>,
@@ -1,10 +1,15 @@
+use crate::pintoken::PinToken;
 use crate::public_key::PublicKey;
 use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
 use crate::public_key_credential_rp_entity::PublicKeyCredentialRpEntity;
 use crate::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
 use crate::str_buf::StrBuf;
+use anyhow::{anyhow, Result};
+use serde_cbor::Value;
 use std::fmt;
 
+pub use crate::credential_management::{CredentialIter, RpIter};
+
 #[derive(Debug, Default, Clone)]
 pub(crate) struct CredentialManagementData {
     pub existing_resident_credentials_count: u32,
@@ -15,9 +20,11 @@ pub(crate) struct CredentialManagementData {
     pub public_key_credential_user_entity: PublicKeyCredentialUserEntity,
     pub public_key_credential_descriptor: PublicKeyCredentialDescriptor,
     pub public_key: PublicKey,
+    pub credential_public_key: Option<Value>,
     pub total_credentials: u32,
     pub cred_protect: u32,
     pub large_blob_key: Vec<u8>,
+    pub third_party_payment: Option<bool>,
 }
 
 #[derive(Debug, Default, Clone)]
@@ -106,6 +113,15 @@ pub struct Credential {
     pub public_key_credential_descriptor: PublicKeyCredentialDescriptor,
     pub public_key: PublicKey,
     pub cred_protect: CredentialProtectionPolicy,
+    /// RP of the credential - the entity is empty if the credentials were enumerated by rpid_hash only
+    pub public_key_credential_rp_entity: PublicKeyCredentialRpEntity,
+    pub rpid_hash: Vec<u8>,
+    /// credential public key in COSE_Key format
+    pub credential_public_key: Option<Value>,
+    /// largeBlobKey (empty if the credential has none)
+    pub large_blob_key: Vec<u8>,
+    /// thirdPartyPayment (CTAP 2.2)
+    pub third_party_payment: Option<bool>,
 }
 impl Credential {
     pub(crate) fn new(d: &CredentialManagementData) -> Credential {
@@ -114,8 +130,18 @@ impl Credential {
             public_key_credential_descriptor: d.public_key_credential_descriptor.clone(),
             public_key: d.public_key.clone(),
             cred_protect: d.cred_protect.into(),
+            credential_public_key: d.credential_public_key.clone(),
+            large_blob_key: d.large_blob_key.to_vec(),
+            third_party_payment: d.third_party_payment,
+            ..Default::default()
         }
     }
+
+    pub(crate) fn with_rp(mut self, rp: &Rp) -> Credential {
+        self.public_key_credential_rp_entity = rp.public_key_credential_rp_entity.clone();
+        self.rpid_hash = rp.rpid_hash.to_vec();
+        self
+    }
 }
 impl fmt::Display for Credential {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
@@ -130,7 +156,79 @@ impl fmt::Display for Credential {
                 &self.public_key_credential_descriptor,
             )
             .append("- public_key", &self.public_key)
-            .append("- cred_protect", &format!("{:?}", self.cred_protect));
+            .append("- cred_protect", &format!("{:?}", self.cred_protect))
+            .append(
+                "- public_key_credential_rp_entity",
+                &self.public_key_credential_rp_entity,
+            )
+            .appenh("- large_blob_key", &self.large_blob_key)
+            .append(
+                "- third_party_payment",
+                &format!("{:?}", self.third_party_payment),
+            );
         write!(f, "{}", strbuf.build())
     }
 }
+
+/// An RP and its discoverable credentials
+#[derive(Debug, Default, Clone)]
+pub struct RpCredentials {
+    pub rp: Rp,
+    pub credentials: Vec<Credential>,
+}
+impl fmt::Display for RpCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", self.rp)?;
+        for (n, credential) in self.credentials.iter().enumerate() {
+            writeln!(f, "- credential[{}]", n)?;
+            write!(f, "{}", credential)?;
+        }
+        Ok(())
+    }
+}
+
+/// persistentPinUvAuthToken with the pcmr permission (CTAP 2.2)
+/// - only for reading credentials (getCredsMetadata, enumerateRPs, enumerateCredentials)
+/// - survives power cycles, so it can be stored (e.g. in a keyring) and used
+///   instead of the PIN until the PIN is changed
+/// - the key is zeroized on drop and not printed by Debug
+pub struct PersistentPinUvAuthToken {
+    pub(crate) pin_token: PinToken,
+}
+impl PersistentPinUvAuthToken {
+    /// Restore a stored token
+    /// - pinUvAuthProtocol 1 and a 32-byte key are supported
+    pub fn new(pin_uv_auth_protocol: u32, key: &[u8]) -> Result<Self> {
+        if pin_uv_auth_protocol != 1 {
+            return Err(anyhow!(
+                "pinUvAuthProtocol {} is not supported.",
+                pin_uv_auth_protocol
+            ));
+        }
+        if key.len() != 32 {
+            return Err(anyhow!(
+                "persistentPinUvAuthToken must be 32 bytes. ({} bytes)",
+                key.len()
+            ));
+        }
+        Ok(PersistentPinUvAuthToken {
+            pin_token: PinToken::with_protocol(key, pin_uv_auth_protocol),
+        })
+    }
+
+    /// The token to store
+    pub fn key(&self) -> &[u8] {
+        &self.pin_token.key
+    }
+
+    pub fn pin_uv_auth_protocol(&self) -> u32 {
+        self.pin_token.pin_uv_auth_protocol
+    }
+}
+impl fmt::Debug for PersistentPinUvAuthToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PersistentPinUvAuthToken")
+            .field("pin_uv_auth_protocol", &self.pin_uv_auth_protocol())
+            .finish_non_exhaustive()
+    }
+}
@@ -38,10 +38,14 @@ pub(crate) fn parse_cbor(
                         .get_id(val)
                         .get_type(val)
                 }
-                0x08 => data.public_key = PublicKey::new(val),
+                0x08 => {
+                    data.public_key = PublicKey::new(val);
+                    data.credential_public_key = Some(val.clone());
+                }
                 0x09 => data.total_credentials = util::cbor_value_to_num(val)?,
                 0x0A => data.cred_protect = util::cbor_value_to_num(val)?,
                 0x0B => data.large_blob_key = util::cbor_value_to_vec_u8(val)?,
+                0x0C => data.third_party_payment = Some(util::cbor_value_to_bool(val)?),
                 _ => println!("parse_cbor_member - unknown member {:?}", member),
             }
         }
@@ -9,7 +9,7 @@ use crate::fidokey::*;
 #[cfg(target_os = "linux")]
 use crate::fidokey_pi::*;
 
-use std::{thread, time};
+use std::{fmt, thread, time};
 
 //pub const USAGE_PAGE_FIDO: u16 = 0xf1d0;
 
@@ -27,10 +27,65 @@ const CTAPHID_CBOR: u8 = CTAP_FRAME_INIT | 0x10;
 const CTAPHID_ERROR: u8 = CTAP_FRAME_INIT | 0x3F;
 const CTAPHID_KEEPALIVE: u8 = CTAP_FRAME_INIT | 0x3B;
 
+// CTAPHID_INIT capabilities flags
+// - CAPABILITY_CBOR : the device implements CTAPHID_CBOR
+pub const CAPABILITY_CBOR: u8 = 0x04;
+
 //const CTAPHID_KEEPALIVE_STATUS_PROCESSING = 1;     // The authenticator is still processing the current request.
 //const CTAPHID_KEEPALIVE_STATUS_UPNEEDED = 2;       // The authenticator is waiting for user presence.
 
+// Error of a CTAPHID_CBOR / CTAPHID_MSG request
+// - status : CTAP status code (CBOR) or SW1 of the U2F status word (MSG) returned by the authenticator,
+//            None if the request failed before the authenticator answered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CtapHidError {
+    pub status: Option<u8>,
+    pub message: String,
+}
+
+impl CtapHidError {
+    fn from_status(status: (u8, u16, u8)) -> Self {
+        CtapHidError {
+            status: Some(status.2),
+            message: format!("response_status err = {}", get_status_message(status)),
+        }
+    }
+
+    pub fn is_status(&self, status: u8) -> bool {
+        self.status == Some(status)
+    }
+}
+
+impl From<String> for CtapHidError {
+    fn from(message: String) -> Self {
+        CtapHidError {
+            status: None,
+            message,
+        }
+    }
+}
+
+impl From<CtapHidError> for String {
+    fn from(err: CtapHidError) -> Self {
+        err.message
+    }
+}
+
+impl fmt::Display for CtapHidError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CtapHidError {}
+
 pub fn ctaphid_init(device: &FidoKeyHid) -> Result<[u8; 4], String> {
+    let (cid, _) = ctaphid_init_with_capabilities(device)?;
+    Ok(cid)
+}
+
+// CID and capabilities flags
+pub fn ctaphid_init_with_capabilities(device: &FidoKeyHid) -> Result<([u8; 4], u8), String> {
     // CTAPHID_INIT
     let mut cmd: [u8; 65] = [0; 65];
 
@@ -65,8 +120,8 @@ pub fn ctaphid_init(device: &FidoKeyHid) -> Result<[u8; 4], String> {
     device.write(&cmd)?;
     let buf = device.read()?;
 
-    // CID
-    Ok([buf[15], buf[16], buf[17], buf[18]])
+    // CID, capabilities flags
+    Ok(([buf[15], buf[16], buf[17], buf[18]], buf[23]))
 }
 
 fn get_responce_status(packet: &[u8]) -> Result<(u8, u16, u8), String> {
@@ -83,11 +138,16 @@ fn get_responce_status(packet: &[u8]) -> Result<(u8, u16, u8), String> {
     // status
     let response_status = if command == CTAPHID_MSG {
         // length check ()
-        if payload_size > packet.len() as u16 {
+        if payload_size < 2 {
             return Err("u2f response size error?".to_string());
         }
-        // U2F(last byte of data)
-        packet[(4 + 2 + payload_size - 1) as usize]
+        if 7 + payload_size as usize > packet.len() {
+            // SW1 is in a continuation packet - checked after all packets are read
+            0x90
+        } else {
+            // U2F(SW1 - second last byte of data)
+            packet[(4 + 2 + payload_size - 1) as usize]
+        }
     } else {
         // CTAP(first byte of data)
         packet[7]
@@ -251,7 +311,7 @@ fn ctaphid_cbormsg(
     cid: &[u8],
     command: u8,
     payload: &[u8],
-) -> Result<Vec<u8>, String> {
+) -> Result<Vec<u8>, CtapHidError> {
     if device.enable_log {
         println!();
         println!("-- send cbor({:02})", payload.len());
@@ -287,10 +347,10 @@ fn ctaphid_cbormsg(
         let buf = match device.read() {
             Ok(res) => res,
             Err(_error) => {
-                return Err(format!(
+                return Err(CtapHidError::from(format!(
                     "read err = {}",
                     ctapdef::get_ctap_status_message(0xfe)
-                ));
+                )));
             }
         };
         //println!("Read: {:?} byte", res);
@@ -324,7 +384,7 @@ fn ctaphid_cbormsg(
     //println!("response_status = 0x{:02X}", st.2);
 
     if is_responce_error(st) {
-        Err(format!("response_status err = {}", get_status_message(st)))
+        Err(CtapHidError::from_status(st))
     } else {
         let mut payload = ctaphid_cbor_responce_get_payload_1(&packet_1st);
 
@@ -336,10 +396,10 @@ fn ctaphid_cbormsg(
                 let buf = match device.read() {
                     Ok(res) => res,
                     Err(_error) => {
-                        return Err(format!(
+                        return Err(CtapHidError::from(format!(
                             "read err = {}",
                             ctapdef::get_ctap_status_message(0xfe)
-                        ));
+                        )));
                     }
                 };
                 //println!("Read: {:?} byte", &buf[..res]);
@@ -356,6 +416,14 @@ fn ctaphid_cbormsg(
             }
         }
 
+        // U2F status word of a response longer than the 1st packet
+        if st.0 == CTAPHID_MSG && payload.len() >= payload_size as usize {
+            st.2 = payload[payload_size as usize - 2];
+            if is_responce_error(st) {
+                return Err(CtapHidError::from_status(st));
+            }
+        }
+
         // get data
         let data = get_data(st, payload);
 
@@ -370,11 +438,19 @@ fn ctaphid_cbormsg(
     }
 }
 
-pub fn ctaphid_cbor(device: &FidoKeyHid, cid: &[u8], payload: &[u8]) -> Result<Vec<u8>, String> {
+pub fn ctaphid_cbor(
+    device: &FidoKeyHid,
+    cid: &[u8],
+    payload: &[u8],
+) -> Result<Vec<u8>, CtapHidError> {
     ctaphid_cbormsg(device, cid, CTAPHID_CBOR, payload)
 }
 
-pub fn ctaphid_msg(device: &FidoKeyHid, cid: &[u8], payload: &[u8]) -> Result<Vec<u8>, String> {
+pub fn ctaphid_msg(
+    device: &FidoKeyHid,
+    cid: &[u8],
+    payload: &[u8],
+) -> Result<Vec<u8>, CtapHidError> {
     ctaphid_cbormsg(device, cid, CTAPHID_MSG, payload)
 }
 
@@ -383,7 +459,7 @@ pub fn ctaphid_xxx(
     cid: &[u8],
     xxx: u8,
     payload: &[u8],
-) -> Result<Vec<u8>, String> {
+) -> Result<Vec<u8>, CtapHidError> {
     ctaphid_cbormsg(device, cid, xxx, payload)
 }
 
@@ -395,7 +471,7 @@ pub fn send_apdu(
     p1: u8,
     p2: u8,
     data: &[u8],
-) -> Result<Vec<u8>, String> {
+) -> Result<Vec<u8>, CtapHidError> {
     /*
     Packs and sends an APDU for use in CTAP1 commands.
     This is a low-level method mainly used internally. Avoid calling it
@@ -10,11 +10,16 @@ pub fn encrypt_message_str(key: &[u8; 32], message: &str) -> Vec<u8> {
 }
 
 pub fn encrypt_message(key: &[u8; 32], message: &[u8]) -> Vec<u8> {
+    encrypt_message_with_iv(key, &[0u8; 16], message)
+}
+
+// AES256-CBC(key,IV,message)
+pub fn encrypt_message_with_iv(key: &[u8; 32], iv: &[u8; 16], message: &[u8]) -> Vec<u8> {
     if message.len() > 4096 {
         panic!("Message too long");
     }
 
-    let cipher = Aes256Cbc::new_from_slices(key, &[0u8; 16]).unwrap();
+    let cipher = Aes256Cbc::new_from_slices(key, iv).unwrap();
     let mut buffer = message.to_vec();
     let ciphertext = cipher.encrypt(&mut buffer, message.len()).unwrap();
 
@@ -27,14 +32,19 @@ pub fn decrypt_message_str(key: &[u8; 32], message: &[u8]) -> String {
 }
 
 pub fn decrypt_message(key: &[u8; 32], message: &[u8]) -> Vec<u8> {
+    decrypt_message_with_iv(key, &[0u8; 16], message)
+}
+
+pub fn decrypt_message_with_iv(key: &[u8; 32], iv: &[u8; 16], message: &[u8]) -> Vec<u8> {
     if message.len() > 4096 {
         panic!("Message too long");
     }
 
-    let cipher = Aes256Cbc::new_from_slices(key, &[0u8; 16]).unwrap();
+    let cipher = Aes256Cbc::new_from_slices(key, iv).unwrap();
     let mut buffer = message.to_vec();
 
-    let plaintext = cipher.decrypt(&mut buffer).unwrap();
-
-    plaintext.to_vec()
+    // decrypt in place - the plaintext is not copied
+    let len = cipher.decrypt(&mut buffer).unwrap().len();
+    buffer.truncate(len);
+    buffer
 }
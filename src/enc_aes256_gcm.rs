@@ -0,0 +1,43 @@
+use ring::aead;
+
+// AES256-GCM(key,nonce,aad,message) -> ciphertext || tag(16)
+pub fn encrypt_message(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    message: &[u8],
+) -> Result<Vec<u8>, String> {
+    let key = create_key(key)?;
+    let mut buffer = message.to_vec();
+    key.seal_in_place_append_tag(
+        aead::Nonce::assume_unique_for_key(*nonce),
+        aead::Aad::from(aad),
+        &mut buffer,
+    )
+    .map_err(|_| "AES256-GCM encrypt error".to_string())?;
+    Ok(buffer)
+}
+
+pub fn decrypt_message(
+    key: &[u8; 32],
+    nonce: &[u8; 12],
+    aad: &[u8],
+    message: &[u8],
+) -> Result<Vec<u8>, String> {
+    let key = create_key(key)?;
+    let mut buffer = message.to_vec();
+    let plaintext = key
+        .open_in_place(
+            aead::Nonce::assume_unique_for_key(*nonce),
+            aead::Aad::from(aad),
+            &mut buffer,
+        )
+        .map_err(|_| "AES256-GCM decrypt error".to_string())?;
+    Ok(plaintext.to_vec())
+}
+
+fn create_key(key: &[u8; 32]) -> Result<aead::LessSafeKey, String> {
+    let unbound_key = aead::UnboundKey::new(&aead::AES_256_GCM, key)
+        .map_err(|_| "AES256-GCM key error".to_string())?;
+    Ok(aead::LessSafeKey::new(unbound_key))
+}
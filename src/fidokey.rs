@@ -4,8 +4,9 @@ use hidapi::HidApi;
 pub struct FidoKeyHid {
     device_internal: hidapi::HidDevice,
     pub enable_log: bool,
-    pub use_pre_bio_enrollment: bool,
-    pub use_pre_credential_management: bool,
+    pub use_pre_bio_enrollment: Option<bool>,
+    pub use_pre_credential_management: Option<bool>,
+    pub pin_retry_floor: Option<u32>,
     pub keep_alive_msg: String,
 }
 
@@ -20,6 +21,7 @@ impl FidoKeyHid {
                         enable_log: cfg.enable_log,
                         use_pre_bio_enrollment: cfg.use_pre_bio_enrollment,
                         use_pre_credential_management: cfg.use_pre_credential_management,
+                        pin_retry_floor: cfg.pin_retry_floor,
                         keep_alive_msg: cfg.keep_alive_msg.to_string(),
                     };
                     return Ok(result);
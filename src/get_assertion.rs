@@ -1,43 +1,241 @@
 use crate::client_pin;
+use crate::client_pin_command::Permission;
+use crate::client_pin_params::{UserVerification, UserVerificationMethod};
 use crate::ctaphid;
 use crate::enc_hmac_sha_256;
 use crate::get_assertion_command;
-use crate::get_assertion_params::Assertion;
 use crate::get_assertion_params::Extension as Gext;
+use crate::get_assertion_params::{Assertion, NoCredentials};
 use crate::get_assertion_response;
+use crate::get_info;
+use crate::get_info_params::Info;
 use crate::get_next_assertion_command;
 use crate::hmac::HmacExt;
+use crate::paging::Paging;
+use crate::pintoken::PinToken;
+use crate::prf_params::PrfOutputs;
+use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
+use crate::u2f;
 use crate::FidoKeyHid;
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
+use std::time::{Duration, Instant};
 
-pub fn get_assertion(
+// The authenticator discards the remaining credentials
+// if authenticatorGetNextAssertion is not received within 30 seconds
+const GET_NEXT_ASSERTION_TIMEOUT: Duration = Duration::from_secs(30);
+
+// authenticatorGetAssertion request
+#[derive(Debug, Default)]
+pub struct Params<'a> {
+    pub rpid: &'a str,
+    pub challenge: &'a [u8],
+    pub allow_list: &'a [PublicKeyCredentialDescriptor],
+    pub pin: Option<&'a str>,
+    pub up: bool,
+    pub uv: Option<bool>,
+    pub user_verification: Option<UserVerification>,
+    pub extensions: Option<&'a Vec<Gext>>,
+}
+
+pub fn get_assertion(device: &FidoKeyHid, params: &Params) -> Result<Vec<Assertion>> {
+    let (cid, hmac_ext, ass) = get_assertion_first(device, params)?;
+
+    let uv_method = ass.user_verification_method;
+    let mut asss = vec![ass];
+    for _ in 0..(asss[0].number_of_credentials - 1) {
+        let mut ass = get_next_assertion(&device, &cid, hmac_ext.as_ref()).map_err(Error::msg)?;
+        ass.user_verification_method = uv_method;
+        asss.push(ass);
+    }
+
+    Ok(asss)
+}
+
+// Lazy version of get_assertion - the device is held until the iterator is dropped
+pub fn get_assertion_iter(device: FidoKeyHid, params: &Params) -> Result<AssertionIter> {
+    let (cid, hmac_ext, ass) = get_assertion_first(&device, params)?;
+
+    let ass_number_of_credentials = ass.number_of_credentials.max(1) as u32;
+    Ok(AssertionIter {
+        device,
+        cid,
+        hmac_ext,
+        user_verification_method: ass.user_verification_method,
+        paging: Paging::new(Some(ass), ass_number_of_credentials),
+        last_response: Instant::now(),
+        prf: false,
+    })
+}
+
+/// Assertions fetched one by one with authenticatorGetNextAssertion.
+/// The session with the authenticator is held until the iterator is dropped.
+/// authenticatorGetNextAssertion must be called within 30 seconds of the previous response.
+pub struct AssertionIter {
+    device: FidoKeyHid,
+    cid: [u8; 4],
+    hmac_ext: Option<HmacExt>,
+    user_verification_method: UserVerificationMethod,
+    paging: Paging<Assertion>,
+    last_response: Instant,
+    pub(crate) prf: bool,
+}
+
+impl AssertionIter {
+    /// numberOfCredentials of the first response (1 if omitted)
+    pub fn number_of_credentials(&self) -> u32 {
+        self.paging.total()
+    }
+
+    /// Number of assertions not yet returned
+    pub fn remaining(&self) -> u32 {
+        self.paging.remaining()
+    }
+
+    /// Time left before the authenticator discards the remaining credentials
+    pub fn time_left(&self) -> Duration {
+        time_left(self.last_response)
+    }
+}
+
+impl Iterator for AssertionIter {
+    type Item = Result<Assertion>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (device, cid, hmac_ext) = (&self.device, &self.cid, self.hmac_ext.as_ref());
+        let last_response = &mut self.last_response;
+        let ass = self.paging.next_with(|| {
+            get_next_in_time(last_response, || {
+                get_next_assertion(device, cid, hmac_ext).map_err(Error::msg)
+            })
+        })?;
+
+        Some(ass.map(|mut ass| {
+            if self.prf {
+                ass.prf = Some(PrfOutputs::from_get_assertion_extensions(&ass.extensions));
+            }
+            ass.user_verification_method = self.user_verification_method;
+            ass
+        }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (0, Some(self.remaining() as usize))
+    }
+}
+
+fn time_left(last_response: Instant) -> Duration {
+    GET_NEXT_ASSERTION_TIMEOUT.saturating_sub(last_response.elapsed())
+}
+
+// authenticatorGetNextAssertion would fail with CTAP2_ERR_NOT_ALLOWED after the timer expired
+pub(crate) fn get_next_in_time<T>(
+    last_response: &mut Instant,
+    get_next: impl FnOnce() -> Result<T>,
+) -> Result<T> {
+    if time_left(*last_response).is_zero() {
+        return Err(anyhow!(
+            "authenticatorGetNextAssertion timer expired. ({} seconds)",
+            GET_NEXT_ASSERTION_TIMEOUT.as_secs()
+        ));
+    }
+    let ret = get_next();
+    *last_response = Instant::now();
+    ret
+}
+
+// authenticatorGetAssertion - returns the channel for authenticatorGetNextAssertion
+fn get_assertion_first(
     device: &FidoKeyHid,
-    rpid: &str,
-    challenge: &[u8],
-    credential_id: &[u8],
-    pin: Option<&str>,
-    up: bool,
-    uv: Option<bool>,
-    extensions: Option<&Vec<Gext>>,
-) -> Result<Vec<Assertion>> {
+    params: &Params,
+) -> Result<([u8; 4], Option<HmacExt>, Assertion)> {
+    let Params {
+        rpid,
+        challenge,
+        allow_list,
+        pin,
+        up,
+        uv,
+        user_verification,
+        extensions,
+    } = *params;
+
     // init
-    let cid = ctaphid::ctaphid_init(&device).map_err(Error::msg)?;
+    let (cid, capabilities) =
+        ctaphid::ctaphid_init_with_capabilities(device).map_err(Error::msg)?;
 
-    let hmac_ext = create_hmacext(&device, &cid, extensions)?;
+    // CTAP1/U2F authenticator
+    // - the implicit uv (without a PIN) is not requested
+    if u2f::is_ctap1_only(capabilities) {
+        if user_verification == Some(UserVerification::Required) || pin.is_some() {
+            return Err(anyhow!(
+                "CTAP1/U2F authenticator does not support user verification."
+            ));
+        }
+        if has_hmac_secret(extensions) {
+            return Err(anyhow!(
+                "CTAP1/U2F authenticator does not support hmac-secret."
+            ));
+        }
+        let ass = u2f::get_assertion(device, rpid, challenge, allow_list, up)?;
+        return Ok((cid, None, ass));
+    }
+
+    let info = if has_hmac_secret(extensions) || allow_list.len() > 1 || user_verification.is_some()
+    {
+        Some(get_info::get_info(device)?)
+    } else {
+        None
+    };
+
+    // user verification
+    let uv_method = match (user_verification, &info) {
+        (Some(policy), Some(info)) => {
+            client_pin::select_user_verification_method(info, policy, pin, None)?
+        }
+        _ => client_pin::user_verification_method_of(pin, uv),
+    };
+    let uv = if user_verification.is_some() {
+        (uv_method == UserVerificationMethod::UvOption).then_some(true)
+    } else {
+        uv
+    };
+
+    // hmac-secret uses the pinUvAuthProtocol preferred by the authenticator
+    let pin_uv_auth_protocol = match &info {
+        Some(info) if has_hmac_secret(extensions) => client_pin::select_pin_uv_auth_protocol(info),
+        _ => 1,
+    };
+
+    let hmac_ext = create_hmacext(&device, &cid, extensions, pin_uv_auth_protocol)?;
 
     // pin token
-    let pin_token = {
-        if let Some(pin) = pin {
-            Some(client_pin::get_pin_token(&device, &cid, pin)?)
-        } else {
-            None
+    let pin_token = client_pin::get_user_verification_token(
+        device,
+        &cid,
+        uv_method,
+        pin,
+        &[Permission::Ga],
+        rpid,
+    )?;
+
+    // allowList
+    let allow_list = match &info {
+        Some(info) if allow_list.len() > 1 => {
+            let list =
+                select_credential_list(device, &cid, rpid, info, allow_list, pin_token.as_ref())?;
+            // none of the credential IDs fits in maxCredentialIdLength
+            if list.is_empty() {
+                return Err(Error::new(NoCredentials));
+            }
+            list
         }
+        _ => allow_list.to_vec(),
     };
 
     // create cmmand
     let send_payload = {
         let mut params =
-            get_assertion_command::Params::new(rpid, challenge.to_vec(), credential_id.to_vec());
+            get_assertion_command::Params::new(rpid, challenge.to_vec(), allow_list.to_vec());
         params.option_up = up;
         params.option_uv = uv;
 
@@ -47,43 +245,205 @@ pub fn get_assertion(
             params.pin_auth = sig[0..16].to_vec();
         }
 
-        get_assertion_command::create_payload(params, hmac_ext)
+        get_assertion_command::create_payload(params, extensions, hmac_ext.as_ref())
     };
 
     // send & response
-    let response_cbor = ctaphid::ctaphid_cbor(&device, &cid, &send_payload).map_err(Error::msg)?;
+    let response_cbor = match ctaphid::ctaphid_cbor(device, &cid, &send_payload) {
+        Ok(response_cbor) => response_cbor,
+        Err(err) if err.is_status(0x2E) && !allow_list.is_empty() => {
+            return Err(Error::new(NoCredentials));
+        }
+        Err(err) => return Err(Error::msg(err)),
+    };
 
-    let ass = get_assertion_response::parse_cbor(&response_cbor).map_err(Error::msg)?;
+    let mut ass = get_assertion_response::parse_cbor(&response_cbor, hmac_ext.as_ref())
+        .map_err(Error::msg)?;
+    ass.user_verification_method = uv_method;
 
-    let mut asss = vec![ass];
-    for _ in 0..(asss[0].number_of_credentials - 1) {
-        let ass = get_next_assertion(&device, &cid).map_err(Error::msg)?;
-        asss.push(ass);
+    // the credential may be omitted if the allowList has only one item
+    if ass.credential_id.is_empty() && allow_list.len() == 1 {
+        ass.credential_id = allow_list[0].id.to_vec();
     }
 
-    Ok(asss)
+    Ok((cid, hmac_ext, ass))
 }
 
-fn get_next_assertion(device: &FidoKeyHid, cid: &[u8]) -> Result<Assertion, String> {
+fn get_next_assertion(
+    device: &FidoKeyHid,
+    cid: &[u8],
+    hmac_ext: Option<&HmacExt>,
+) -> Result<Assertion, String> {
     let send_payload = get_next_assertion_command::create_payload();
     let response_cbor = ctaphid::ctaphid_cbor(device, cid, &send_payload)?;
-    get_assertion_response::parse_cbor(&response_cbor)
+    get_assertion_response::parse_cbor(&response_cbor, hmac_ext)
+}
+
+// allowList / excludeList to send
+// - credential IDs longer than maxCredentialIdLength cannot belong to this authenticator
+// - a list that fits in maxCredentialCountInList is sent as it is
+// - a longer list is pre-flighted (silent assertions with the pinUvAuthParam, if any)
+//   in batches of maxCredentialCountInList, and only the credential found is sent
+pub(crate) fn select_credential_list(
+    device: &FidoKeyHid,
+    cid: &[u8],
+    rpid: &str,
+    info: &Info,
+    list: &[PublicKeyCredentialDescriptor],
+    pin_token: Option<&PinToken>,
+) -> Result<Vec<PublicKeyCredentialDescriptor>> {
+    let batches = probe_batches(info, list);
+    if fits_in_one_request(info, &batches) {
+        return Ok(batches.concat());
+    }
+    let found = find_credential_in_batches(device, cid, rpid, &batches, pin_token)?;
+    Ok(credential_list_to_send(batches, found))
+}
+
+// maxCredentialCountInList not reported - no limit
+pub(crate) fn fits_in_one_request(
+    info: &Info,
+    batches: &[Vec<PublicKeyCredentialDescriptor>],
+) -> bool {
+    info.max_credential_count_in_list == 0 || batches.len() <= 1
+}
+
+// The credential found by the pre-flight.
+// If none was found, the first batch is sent and the authenticator decides,
+// e.g. a credential that requires user verification the pre-flight could not provide.
+pub(crate) fn credential_list_to_send(
+    batches: Vec<Vec<PublicKeyCredentialDescriptor>>,
+    found: Option<PublicKeyCredentialDescriptor>,
+) -> Vec<PublicKeyCredentialDescriptor> {
+    match found {
+        Some(found) => vec![found],
+        None => batches.into_iter().next().unwrap_or_default(),
+    }
+}
+
+// Returns the first credential of the list found on the authenticator.
+pub(crate) fn find_credential_in_list(
+    device: &FidoKeyHid,
+    cid: &[u8],
+    rpid: &str,
+    info: &Info,
+    list: &[PublicKeyCredentialDescriptor],
+) -> Result<Option<PublicKeyCredentialDescriptor>> {
+    find_credential_in_batches(device, cid, rpid, &probe_batches(info, list), None)
+}
+
+fn find_credential_in_batches(
+    device: &FidoKeyHid,
+    cid: &[u8],
+    rpid: &str,
+    batches: &[Vec<PublicKeyCredentialDescriptor>],
+    pin_token: Option<&PinToken>,
+) -> Result<Option<PublicKeyCredentialDescriptor>> {
+    for batch in batches {
+        if let Some(found) = find_credential(device, cid, rpid, batch, pin_token)? {
+            return Ok(Some(found));
+        }
+    }
+    Ok(None)
+}
+
+// Batches of maxCredentialCountInList (one by one if the authenticator does not report it)
+// without the credential IDs longer than maxCredentialIdLength
+pub(crate) fn probe_batches(
+    info: &Info,
+    list: &[PublicKeyCredentialDescriptor],
+) -> Vec<Vec<PublicKeyCredentialDescriptor>> {
+    let max_id_length = info.max_credential_id_length as usize;
+    let list: Vec<PublicKeyCredentialDescriptor> = list
+        .iter()
+        .filter(|x| max_id_length == 0 || x.id.len() <= max_id_length)
+        .cloned()
+        .collect();
+    let batch_size = match info.max_credential_count_in_list as usize {
+        0 => 1,
+        n => n,
+    };
+    list.chunks(batch_size)
+        .map(|batch| batch.to_vec())
+        .collect()
+}
+
+// Silent (up=false) getAssertion to find which of the list exists on the authenticator.
+// No user interaction is required.
+// Credentials that require user verification (credProtect) are found only with a pinUvAuthToken.
+pub(crate) fn find_credential(
+    device: &FidoKeyHid,
+    cid: &[u8],
+    rpid: &str,
+    list: &[PublicKeyCredentialDescriptor],
+    pin_token: Option<&PinToken>,
+) -> Result<Option<PublicKeyCredentialDescriptor>> {
+    let mut params = get_assertion_command::Params::new(rpid, b"probe".to_vec(), list.to_vec());
+    params.option_up = false;
+    if let Some(pin_token) = pin_token {
+        params.pin_auth = pin_token.authenticate(&params.client_data_hash);
+    }
+    let send_payload = get_assertion_command::create_payload(params, None, None);
+
+    match ctaphid::ctaphid_cbor(device, cid, &send_payload) {
+        Ok(response_cbor) => {
+            let ass =
+                get_assertion_response::parse_cbor(&response_cbor, None).map_err(Error::msg)?;
+            Ok(matched_credential(list, &ass.credential_id))
+        }
+        Err(err) if err.is_status(0x2E) => Ok(None),
+        Err(err) => Err(Error::msg(err)),
+    }
+}
+
+// The credential of the list that the assertion was made with
+// - the credential may be omitted if the allowList has only one item
+pub(crate) fn matched_credential(
+    list: &[PublicKeyCredentialDescriptor],
+    credential_id: &[u8],
+) -> Option<PublicKeyCredentialDescriptor> {
+    if credential_id.is_empty() && list.len() == 1 {
+        Some(list[0].clone())
+    } else {
+        list.iter().find(|x| x.id == credential_id).cloned()
+    }
+}
+
+// find_credential_in_list on a new channel
+pub(crate) fn find_allowed_credential(
+    device: &FidoKeyHid,
+    rpid: &str,
+    allow_list: &[PublicKeyCredentialDescriptor],
+) -> Result<Option<PublicKeyCredentialDescriptor>> {
+    let info = get_info::get_info(device)?;
+    let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+    find_credential_in_list(device, &cid, rpid, &info, allow_list)
+}
+
+fn has_hmac_secret(extensions: Option<&Vec<Gext>>) -> bool {
+    extensions
+        .iter()
+        .flat_map(|exts| exts.iter())
+        .any(|ext| matches!(ext, Gext::HmacSecret(_) | Gext::HmacSecret2(_)))
 }
 
 fn create_hmacext(
     device: &FidoKeyHid,
     cid: &[u8; 4],
     extensions: Option<&Vec<Gext>>,
+    pin_uv_auth_protocol: u32,
 ) -> Result<Option<HmacExt>> {
     if let Some(extensions) = extensions {
         for ext in extensions {
-            match ext {
-                Gext::HmacSecret(n) => {
-                    let mut hmac_ext = HmacExt::default();
-                    hmac_ext.create(device, cid, &n.unwrap(), None)?;
-                    return Ok(Some(hmac_ext));
-                }
-            }
+            let (salt1, salt2) = match ext {
+                Gext::HmacSecret(n) => (n, &None),
+                Gext::HmacSecret2((n1, n2)) => (n1, n2),
+                _ => continue,
+            };
+            let salt1 = salt1.ok_or_else(|| anyhow!("hmac-secret salt1 is missing."))?;
+            let mut hmac_ext = HmacExt::default();
+            hmac_ext.create(device, cid, &salt1, salt2.as_ref(), pin_uv_auth_protocol)?;
+            return Ok(Some(hmac_ext));
         }
         Ok(None)
     } else {
@@ -1,6 +1,7 @@
 use crate::ctapdef;
 use crate::get_assertion_params::Extension;
 use crate::hmac::HmacExt;
+use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
 use crate::util;
 use serde_cbor::to_vec;
 use serde_cbor::Value;
@@ -10,24 +11,32 @@ use std::collections::BTreeMap;
 pub struct Params {
     pub rp_id: String,
     pub client_data_hash: Vec<u8>,
-    pub allowlist_credential_id: Vec<u8>,
+    pub allow_list: Vec<PublicKeyCredentialDescriptor>,
     pub option_up: bool,
     pub option_uv: Option<bool>,
     pub pin_auth: Vec<u8>,
 }
 
 impl Params {
-    pub fn new(rp_id: &str, challenge: Vec<u8>, credential_id: Vec<u8>) -> Params {
+    pub fn new(
+        rp_id: &str,
+        challenge: Vec<u8>,
+        allow_list: Vec<PublicKeyCredentialDescriptor>,
+    ) -> Params {
         Params {
             rp_id: rp_id.to_string(),
             client_data_hash: util::create_clientdata_hash(challenge),
-            allowlist_credential_id: credential_id.to_vec(),
+            allow_list,
             ..Default::default()
         }
     }
 }
 
-pub fn create_payload(params: Params, hmac_ext: Option<HmacExt>) -> Vec<u8> {
+pub fn create_payload(
+    params: Params,
+    extensions: Option<&Vec<Extension>>,
+    hmac_ext: Option<&HmacExt>,
+) -> Vec<u8> {
     // 0x01 : rpid
     let rpid = Value::Text(params.rp_id.to_string());
 
@@ -36,50 +45,49 @@ pub fn create_payload(params: Params, hmac_ext: Option<HmacExt>) -> Vec<u8> {
 
     // 0x03 : allowList
     let allow_list = {
-        if !params.allowlist_credential_id.is_empty() {
-            let mut allow_list_val = BTreeMap::new();
-            allow_list_val.insert(
-                Value::Text("id".to_string()),
-                Value::Bytes(params.allowlist_credential_id),
-            );
-            allow_list_val.insert(
-                Value::Text("type".to_string()),
-                Value::Text("public-key".to_string()),
-            );
-            let tmp = Value::Map(allow_list_val);
-            let allow_list = Value::Array(vec![tmp]);
-            Some(allow_list)
+        if !params.allow_list.is_empty() {
+            let allow_list = params.allow_list.iter().map(|x| x.to_value()).collect();
+            Some(Value::Array(allow_list))
         } else {
             None
         }
     };
 
-    // 0x04 : HMAC Secret Extension
+    // 0x04 : extensions
     let mut ext_val = BTreeMap::new();
 
-    let extensions = {
-        if let Some(hmac_ext) = hmac_ext {
-            let mut param = BTreeMap::new();
-
-            // keyAgreement(0x01)
-            let val = hmac_ext.shared_secret.public_key.to_value().unwrap();
-            param.insert(Value::Integer(0x01), val);
-
-            // saltEnc(0x02)
-            param.insert(Value::Integer(0x02), Value::Bytes(hmac_ext.salt_enc));
-
-            // saltAuth(0x03)
-            param.insert(Value::Integer(0x03), Value::Bytes(hmac_ext.salt_auth));
+    if let Some(extensions) = extensions {
+        for ext in extensions {
+            match ext {
+                Extension::LargeBlobKey(n) => {
+                    if let Some(large_blob_key) = n {
+                        ext_val.insert(Value::Text(ext.to_string()), Value::Bool(*large_blob_key));
+                    }
+                }
+                Extension::CredBlob((n, _)) => {
+                    if let Some(cred_blob) = n {
+                        ext_val.insert(Value::Text(ext.to_string()), Value::Bool(*cred_blob));
+                    }
+                }
+                Extension::HmacSecret(_) | Extension::HmacSecret2(_) => (),
+            }
+        }
+    }
 
+    // HMAC Secret Extension
+    {
+        if let Some(hmac_ext) = hmac_ext {
             ext_val.insert(
                 Value::Text(Extension::HmacSecret(None).to_string()),
-                Value::Map(param),
+                hmac_ext.to_value(),
             );
-
-            Some(Value::Map(ext_val))
-        } else {
-            None
         }
+    }
+
+    let extensions = if !ext_val.is_empty() {
+        Some(Value::Map(ext_val))
+    } else {
+        None
     };
 
     // 0x05 : options
@@ -2,7 +2,9 @@
 get_assertion API parameters
 */
 
-use crate::auth_data::Flags;
+use crate::auth_data::{AuthenticatorData, Flags};
+use crate::client_pin_params::UserVerificationMethod;
+use crate::prf_params::PrfOutputs;
 use crate::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
 use crate::str_buf::StrBuf;
 use ring::digest;
@@ -10,6 +12,8 @@ use std::convert::TryFrom;
 use std::fmt;
 use strum_macros::AsRefStr;
 
+pub use crate::get_assertion::AssertionIter;
+
 /// Assertion Object
 #[derive(Debug, Default, Clone)]
 pub struct Assertion {
@@ -23,6 +27,13 @@ pub struct Assertion {
     pub extensions: Vec<Extension>,
     // row - audh_data
     pub auth_data: Vec<u8>,
+    /// parsed auth_data
+    pub authenticator_data: AuthenticatorData,
+    pub large_blob_key: Vec<u8>,
+    /// WebAuthn prf extension outputs (only if prf was requested)
+    pub prf: Option<PrfOutputs>,
+    /// how the user was verified
+    pub user_verification_method: UserVerificationMethod,
 }
 
 impl fmt::Display for Assertion {
@@ -35,7 +46,13 @@ impl fmt::Display for Assertion {
             .append("- number_of_credentials", &self.number_of_credentials)
             .appenh("- signature", &self.signature)
             .append("- user", &self.user)
-            .appenh("- credential_id", &self.credential_id);
+            .appenh("- credential_id", &self.credential_id)
+            .appenh("- large_blob_key", &self.large_blob_key)
+            .append("- user_verification_method", &self.user_verification_method);
+
+        if let Some(prf) = &self.prf {
+            strbuf.appent("- prf").add(&format!("{}", prf));
+        }
 
         for e in &self.extensions {
             match e {
@@ -45,6 +62,22 @@ impl fmt::Display for Assertion {
                         strbuf.appenh(&tmp, &output1_enc.to_vec());
                     }
                 }
+                Extension::HmacSecret2((d1, d2)) => {
+                    let tmp = format!("- {}", Extension::HmacSecret(None));
+                    if let Some(output1) = d1 {
+                        strbuf.appenh(&format!("{} output1", tmp), output1);
+                    }
+                    if let Some(output2) = d2 {
+                        strbuf.appenh(&format!("{} output2", tmp), output2);
+                    }
+                }
+                Extension::LargeBlobKey(_) => {}
+                Extension::CredBlob((_, d)) => {
+                    if let Some(cred_blob) = d {
+                        let tmp = format!("- {}", Extension::CredBlob((None, None)));
+                        strbuf.appenh(&tmp, cred_blob);
+                    }
+                }
             }
         }
 
@@ -52,10 +85,31 @@ impl fmt::Display for Assertion {
     }
 }
 
+/// CTAP2_ERR_NO_CREDENTIALS(0x2E) - none of the allowList credentials exists on the authenticator
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoCredentials;
+
+impl fmt::Display for NoCredentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "No credentials: none of the allowList credentials exists on the authenticator."
+        )
+    }
+}
+
+impl std::error::Error for NoCredentials {}
+
 #[derive(Debug, Clone, strum_macros::Display, AsRefStr)]
 pub enum Extension {
     #[strum(serialize = "hmac-secret")]
-    HmacSecret(Option<[u8; 32]>),
+    HmacSecret(Option<[u8; 32]>), // input: salt1 / output: output1
+    #[strum(serialize = "hmac-secret")]
+    HmacSecret2((Option<[u8; 32]>, Option<[u8; 32]>)), // input: salt1, salt2 / output: output1, output2
+    #[strum(serialize = "largeBlobKey")]
+    LargeBlobKey(Option<bool>), // "largeBlobKey": true
+    #[strum(serialize = "credBlob")]
+    CredBlob((Option<bool>, Option<Vec<u8>>)), // input: "credBlob": true / output: credBlob
 }
 
 impl Extension {
@@ -63,4 +117,13 @@ impl Extension {
         let hasher = digest::digest(&digest::SHA256, message.as_bytes());
         Extension::HmacSecret(Some(<[u8; 32]>::try_from(hasher.as_ref()).unwrap()))
     }
+
+    pub fn create_hmac_secret2_from_string(message1: &str, message2: &str) -> Extension {
+        let hasher1 = digest::digest(&digest::SHA256, message1.as_bytes());
+        let hasher2 = digest::digest(&digest::SHA256, message2.as_bytes());
+        Extension::HmacSecret2((
+            Some(<[u8; 32]>::try_from(hasher1.as_ref()).unwrap()),
+            Some(<[u8; 32]>::try_from(hasher2.as_ref()).unwrap()),
+        ))
+    }
 }
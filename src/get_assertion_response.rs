@@ -1,73 +1,60 @@
-use crate::auth_data::Flags;
+use crate::auth_data::AuthenticatorData;
 use crate::get_assertion_params;
 use crate::get_assertion_params::Extension;
+use crate::hmac::HmacExt;
 use crate::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
 use crate::util;
-use byteorder::{BigEndian, ReadBytesExt};
 use serde_cbor::Value;
-use std::io::Cursor;
 
 fn parse_cbor_authdata(
     authdata: Vec<u8>,
     ass: &mut get_assertion_params::Assertion,
+    hmac_ext: Option<&HmacExt>,
 ) -> Result<(), String> {
-    // copy
-    ass.auth_data = authdata.to_vec();
-
-    let mut index = 0;
-
-    let clo_vec = |idx: usize, x: usize| (authdata[idx..idx + x].to_vec(), idx + x);
-
-    // rpIdHash	(32)
-    let ret = clo_vec(index, 32);
-    ass.rpid_hash = ret.0;
-    index = ret.1;
+    let auth_data = AuthenticatorData::parse(&authdata).map_err(|e| e.to_string())?;
+    ass.rpid_hash = auth_data.rpid_hash.to_vec();
+    ass.flags = auth_data.flags.clone();
+    ass.sign_count = auth_data.sign_count;
 
-    // flags(1)
-    let byte = authdata[index];
-    ass.flags = Flags::parse(byte).unwrap();
-    index += 1;
+    for (member, val) in &auth_data.extensions {
+        if *member == Extension::HmacSecret(None).to_string() {
+            let v = util::cbor_value_to_vec_u8(val)?;
+            ass.extensions.push(parse_hmac_secret(&v, hmac_ext)?);
+        } else if *member == Extension::CredBlob((None, None)).to_string() {
+            let v = util::cbor_value_to_vec_u8(val)?;
+            ass.extensions.push(Extension::CredBlob((None, Some(v))));
+        }
+    }
 
-    // signCount(4)
-    let clo = |idx: usize, x: usize| {
-        let mut rdr = Cursor::new(authdata[idx..idx + x].to_vec());
-        (rdr.read_u32::<BigEndian>().unwrap(), idx + x)
-    };
-    let ret = clo(index, 4);
-    ass.sign_count = ret.0;
-    index = ret.1;
+    ass.authenticator_data = auth_data;
+    // copy
+    ass.auth_data = authdata;
+    Ok(())
+}
 
-    // rest is cbor objects
-    // - [0] credentialPublicKey
-    // - [1] extensions
-    let slice = if ass.flags.attested_credential_data_included {
-        let slice = &authdata[index..authdata.len()];
-        let deserializer = serde_cbor::Deserializer::from_slice(slice);
-        //
-        slice[deserializer.byte_offset()..].to_vec()
+// hmac-secret output
+// - without HmacExt : the first 32 bytes of the encrypted output as they are
+// - with HmacExt : decrypt(shared secret, output) → output1 (|| output2)
+fn parse_hmac_secret(output_enc: &[u8], hmac_ext: Option<&HmacExt>) -> Result<Extension, String> {
+    if let Some(hmac_ext) = hmac_ext {
+        match hmac_ext.decrypt_output(output_enc)? {
+            (output1, Some(output2)) => Ok(Extension::HmacSecret2((Some(output1), Some(output2)))),
+            (output1, None) => Ok(Extension::HmacSecret(Some(output1))),
+        }
     } else {
-        authdata[index..authdata.len()].to_vec()
-    };
-
-    if ass.flags.extension_data_included {
-        //println!("{:02} - {:?}", slice.len(), util::to_hex_str(&slice));
-        let maps = util::cbor_bytes_to_map(&slice)?;
-        for (key, val) in &maps {
-            if let Value::Text(member) = key {
-                if *member == Extension::HmacSecret(None).to_string() {
-                    let v = util::cbor_value_to_vec_u8(val)?;
-                    let mut hmac_secret_0 = [0u8; 32];
-                    hmac_secret_0.copy_from_slice(&v[0..32]);
-                    ass.extensions
-                        .push(Extension::HmacSecret(Some(hmac_secret_0)));
-                }
-            }
+        if output_enc.len() < 32 {
+            return Err("hmac-secret output length error".to_string());
         }
-    };
-    Ok(())
+        let mut output1 = [0u8; 32];
+        output1.copy_from_slice(&output_enc[0..32]);
+        Ok(Extension::HmacSecret(Some(output1)))
+    }
 }
 
-pub fn parse_cbor(bytes: &[u8]) -> Result<get_assertion_params::Assertion, String> {
+pub fn parse_cbor(
+    bytes: &[u8],
+    hmac_ext: Option<&HmacExt>,
+) -> Result<get_assertion_params::Assertion, String> {
     let mut ass = get_assertion_params::Assertion::default();
     let maps = util::cbor_bytes_to_map(bytes)?;
     for (key, val) in &maps {
@@ -76,7 +63,7 @@ pub fn parse_cbor(bytes: &[u8]) -> Result<get_assertion_params::Assertion, Strin
                 0x01 => ass.credential_id = util::cbor_get_bytes_from_map(val, "id")?,
                 0x02 => {
                     if let Value::Bytes(xs) = val {
-                        parse_cbor_authdata(xs.to_vec(), &mut ass)?;
+                        parse_cbor_authdata(xs.to_vec(), &mut ass, hmac_ext)?;
                     }
                 }
                 0x03 => ass.signature = util::cbor_value_to_vec_u8(val)?,
@@ -87,6 +74,7 @@ pub fn parse_cbor(bytes: &[u8]) -> Result<get_assertion_params::Assertion, Strin
                         .get_display_name(val)
                 }
                 0x05 => ass.number_of_credentials = util::cbor_value_to_num(val)?,
+                0x07 => ass.large_blob_key = util::cbor_value_to_vec_u8(val)?,
                 _ => println!("- anything error"),
             }
         }
@@ -2,6 +2,7 @@ use crate::ctaphid;
 use crate::get_info_command;
 use crate::get_info_params;
 use crate::get_info_response;
+use crate::u2f_command;
 use crate::FidoKeyHid;
 use anyhow::{anyhow, Error, Result};
 
@@ -18,8 +19,7 @@ pub fn get_info_u2f(device: &FidoKeyHid) -> Result<String> {
 
     let _data: Vec<u8> = Vec::new();
 
-    // CTAP1_INS.Version = 3
-    match ctaphid::send_apdu(device, &cid, 0, 3, 0, 0, &_data) {
+    match ctaphid::send_apdu(device, &cid, 0, u2f_command::U2F_VERSION, 0, 0, &_data) {
         Ok(result) => {
             let version: String = String::from_utf8(result).unwrap();
             Ok(version)
@@ -2,7 +2,10 @@
 get_info API parameters
 */
 
+use crate::public_key_credential_parameters::PublicKeyCredentialParameters;
 use crate::str_buf::StrBuf;
+use serde_cbor::Value;
+use std::collections::BTreeMap;
 use std::fmt;
 
 #[derive(Debug, Default)]
@@ -11,7 +14,8 @@ pub struct Info {
     pub versions: Vec<String>,
     pub extensions: Vec<String>,
     pub aaguid: Vec<u8>,
-    pub options: Vec<(String, bool)>,
+    /// option id → value (absent options are not in the map)
+    pub options: BTreeMap<String, bool>,
     pub max_msg_size: i32,
     //pub pin_protocols: Vec<i32>,
     // CTAP 2.1
@@ -19,7 +23,7 @@ pub struct Info {
     pub max_credential_count_in_list: u32,
     pub max_credential_id_length: u32,
     pub transports: Vec<String>,
-    pub algorithms: Vec<(String, String)>,
+    pub algorithms: Vec<PublicKeyCredentialParameters>,
     pub max_serialized_large_blob_array: u32,
     pub force_pin_change: bool,
     pub min_pin_length: u32,
@@ -28,7 +32,43 @@ pub struct Info {
     pub max_rpids_for_set_min_pin_length: u32,
     pub preferred_platform_uv_attempts: u32,
     pub uv_modality: u32,
+    pub certifications: BTreeMap<String, u32>,
     pub remaining_discoverable_credentials: u32,
+    // CTAP 2.2
+    pub vendor_prototype_config_commands: Vec<u64>,
+    pub attestation_formats: Vec<String>,
+    pub uv_count_since_last_pin_entry: Option<u32>,
+    pub long_touch_for_reset: bool,
+    pub enc_identifier: Vec<u8>,
+    pub transports_for_reset: Vec<String>,
+    pub pin_complexity_policy: Option<bool>,
+    pub pin_complexity_policy_url: Vec<u8>,
+    pub max_pin_length: u32,
+    /// members this library does not know yet (key → raw value)
+    pub unknown: BTreeMap<i128, Value>,
+}
+
+impl Info {
+    /// - Some(true) : present and set to true
+    /// - Some(false) : present and set to false
+    /// - None : absent
+    pub fn option(&self, id: &str) -> Option<bool> {
+        self.options.get(id).copied()
+    }
+
+    // a command of FIDO_2_1 or its prototype of FIDO_2_1_PRE
+    // - option : FIDO_2_1
+    // - preview_option : FIDO_2_1_PRE
+    // - neither : FIDO_2_1 if the authenticator reports it
+    pub(crate) fn is_prototype(&self, option: &str, preview_option: &str) -> bool {
+        if self.option(option).is_some() {
+            false
+        } else if self.option(preview_option).is_some() {
+            true
+        } else {
+            !self.versions.iter().any(|v| v == "FIDO_2_1")
+        }
+    }
 }
 
 impl fmt::Display for Info {
@@ -50,7 +90,17 @@ impl fmt::Display for Info {
             )
             .append("- max_credential_id_length", &self.max_credential_id_length)
             .append("- transports", &format!("{:?}", self.transports))
-            .append("- algorithms", &format!("{:?}", self.algorithms))
+            .append(
+                "- algorithms",
+                &format!(
+                    "[{}]",
+                    self.algorithms
+                        .iter()
+                        .map(|x| x.to_string())
+                        .collect::<Vec<String>>()
+                        .join(" , ")
+                ),
+            )
             .append(
                 "- max_serialized_large_blob_array",
                 &format!("{:?}", self.max_serialized_large_blob_array),
@@ -77,10 +127,42 @@ impl fmt::Display for Info {
                 &format!("{:?}", self.preferred_platform_uv_attempts),
             )
             .append("- uv_modality", &format!("{:?}", self.uv_modality))
+            .append("- certifications", &format!("{:?}", self.certifications))
             .append(
                 "- remaining_discoverable_credentials",
                 &format!("{:?}", self.remaining_discoverable_credentials),
-            );
+            )
+            .append(
+                "- vendor_prototype_config_commands",
+                &format!("{:?}", self.vendor_prototype_config_commands),
+            )
+            .append(
+                "- attestation_formats",
+                &format!("{:?}", self.attestation_formats),
+            )
+            .append(
+                "- uv_count_since_last_pin_entry",
+                &format!("{:?}", self.uv_count_since_last_pin_entry),
+            )
+            .append("- long_touch_for_reset", &self.long_touch_for_reset)
+            .appenh("- enc_identifier", &self.enc_identifier)
+            .append(
+                "- transports_for_reset",
+                &format!("{:?}", self.transports_for_reset),
+            )
+            .append(
+                "- pin_complexity_policy",
+                &format!("{:?}", self.pin_complexity_policy),
+            )
+            .append(
+                "- pin_complexity_policy_url",
+                &String::from_utf8_lossy(&self.pin_complexity_policy_url),
+            )
+            .append("- max_pin_length", &self.max_pin_length);
+
+        for (key, val) in &self.unknown {
+            strbuf.append(&format!("- unknown 0x{:02X}", key), &format!("{:?}", val));
+        }
 
         write!(f, "{}", strbuf.build())
     }
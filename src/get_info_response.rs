@@ -1,4 +1,5 @@
 use crate::get_info_params;
+use crate::public_key_credential_parameters::PublicKeyCredentialParameters;
 use crate::util;
 use serde_cbor::Value;
 
@@ -14,10 +15,8 @@ pub fn parse_cbor(bytes: &[u8]) -> Result<get_info_params::Info, String> {
                 0x04 => {
                     if let Value::Map(xs) = val {
                         for (key, val) in xs {
-                            if let Value::Text(s) = key {
-                                if let Value::Bool(b) = val {
-                                    info.options.push((s.to_string(), *b));
-                                }
+                            if let (Value::Text(s), Value::Bool(b)) = (key, val) {
+                                info.options.insert(s.to_string(), *b);
                             }
                         }
                     }
@@ -36,29 +35,11 @@ pub fn parse_cbor(bytes: &[u8]) -> Result<get_info_params::Info, String> {
                 0x0A => {
                     if let Value::Array(xs) = val {
                         for x in xs {
-                            if let Value::Map(n) = x {
-                                for (key, val) in n {
-                                    let setkey = {
-                                        if let Value::Text(keystr) = key {
-                                            keystr.to_string()
-                                        } else {
-                                            "".to_string()
-                                        }
-                                    };
-
-                                    let setval = {
-                                        if let Value::Text(valstr) = val {
-                                            valstr.to_string()
-                                        } else if let Value::Integer(valint) = val {
-                                            valint.to_string()
-                                        } else {
-                                            "".to_string()
-                                        }
-                                    };
-
-                                    info.algorithms.push((setkey, setval));
-                                }
-                            }
+                            info.algorithms.push(
+                                PublicKeyCredentialParameters::default()
+                                    .get_type(x)
+                                    .get_alg(x),
+                            );
                         }
                     }
                 }
@@ -70,8 +51,37 @@ pub fn parse_cbor(bytes: &[u8]) -> Result<get_info_params::Info, String> {
                 0x10 => info.max_rpids_for_set_min_pin_length = util::cbor_value_to_num(val)?,
                 0x11 => info.preferred_platform_uv_attempts = util::cbor_value_to_num(val)?,
                 0x12 => info.uv_modality = util::cbor_value_to_num(val)?,
+                0x13 => {
+                    if let Value::Map(xs) = val {
+                        for (key, val) in xs {
+                            info.certifications.insert(
+                                util::cbor_value_to_str(key)?,
+                                util::cbor_value_to_num(val)?,
+                            );
+                        }
+                    }
+                }
                 0x14 => info.remaining_discoverable_credentials = util::cbor_value_to_num(val)?,
-                _ => println!("parse_cbor_member - unknown info {:?}", member),
+                // CTAP 2.2
+                0x15 => {
+                    if let Value::Array(xs) = val {
+                        for x in xs {
+                            info.vendor_prototype_config_commands
+                                .push(util::cbor_value_to_num(x)?);
+                        }
+                    }
+                }
+                0x16 => info.attestation_formats = util::cbor_value_to_vec_string(val)?,
+                0x17 => info.uv_count_since_last_pin_entry = Some(util::cbor_value_to_num(val)?),
+                0x18 => info.long_touch_for_reset = util::cbor_value_to_bool(val)?,
+                0x19 => info.enc_identifier = util::cbor_value_to_vec_u8(val)?,
+                0x1A => info.transports_for_reset = util::cbor_value_to_vec_string(val)?,
+                0x1B => info.pin_complexity_policy = Some(util::cbor_value_to_bool(val)?),
+                0x1C => info.pin_complexity_policy_url = util::cbor_value_to_vec_u8(val)?,
+                0x1D => info.max_pin_length = util::cbor_value_to_num(val)?,
+                _ => {
+                    info.unknown.insert(*member, val.clone());
+                }
             }
         }
     }
@@ -1,14 +1,12 @@
-use crate::client_pin_command;
-use crate::client_pin_command::SubCommand as PinCmd;
-use crate::client_pin_response;
-use crate::ctaphid;
-use crate::enc_aes256_cbc;
-use crate::enc_hmac_sha_256;
+use crate::client_pin;
 use crate::ss::SharedSecret;
 use crate::FidoKeyHid;
 use anyhow::{Error, Result};
+use serde_cbor::Value;
+use std::collections::BTreeMap;
+use zeroize::Zeroizing;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default)]
 pub struct HmacExt {
     pub shared_secret: SharedSecret,
     pub salt_enc: Vec<u8>,
@@ -21,24 +19,23 @@ impl HmacExt {
         device: &FidoKeyHid,
         cid: &[u8],
         salt1: &[u8; 32],
-        _salt2: Option<&[u8; 32]>,
+        salt2: Option<&[u8; 32]>,
+        pin_uv_auth_protocol: u32,
     ) -> Result<()> {
         //println!("----------");
         //println!("{}", StrBuf::bufh("salt1", salt1));
 
-        let send_payload =
-            client_pin_command::create_payload(PinCmd::GetKeyAgreement).map_err(Error::msg)?;
-        let response_cbor =
-            ctaphid::ctaphid_cbor(device, cid, &send_payload).map_err(Error::msg)?;
-
-        let key_agreement =
-            client_pin_response::parse_cbor_client_pin_get_keyagreement(&response_cbor)
-                .map_err(Error::msg)?;
+        let key_agreement = client_pin::get_authenticator_key_agreement_with_protocol(
+            device,
+            cid,
+            pin_uv_auth_protocol,
+        )?;
 
         //println!("key_agreement");
         //println!("{}", self.key_agreement);
 
-        self.shared_secret = SharedSecret::new(&key_agreement).map_err(Error::msg)?;
+        self.shared_secret = SharedSecret::new_with_protocol(&key_agreement, pin_uv_auth_protocol)
+            .map_err(Error::msg)?;
 
         // saltEnc
         //  Encryption of the one or two salts (called salt1 (32 bytes)
@@ -48,14 +45,70 @@ impl HmacExt {
         //  encrypt(key, demPlaintext) → ciphertext
         //      Encrypts a plaintext to produce a ciphertext, which may be longer than the plaintext.
         //      The plaintext is restricted to being a multiple of the AES block size (16 bytes) in length.
-        self.salt_enc = enc_aes256_cbc::encrypt_message(&self.shared_secret.secret, salt1);
+        // reserve both salts up front so extending does not leave a stale copy behind
+        let mut salts = Zeroizing::new(Vec::with_capacity(64));
+        salts.extend_from_slice(salt1);
+        if let Some(salt2) = salt2 {
+            salts.extend_from_slice(salt2);
+        }
+        self.salt_enc = self
+            .shared_secret
+            .encrypt_message(&salts)
+            .map_err(Error::msg)?;
         //println!("{}", StrBuf::bufh("salt_enc", &self.salt_enc));
 
         // saltAuth
-        let sig = enc_hmac_sha_256::authenticate(&self.shared_secret.secret, &self.salt_enc);
-        self.salt_auth = sig[0..16].to_vec();
+        //  authenticate(shared secret, saltEnc)
+        self.salt_auth = self.shared_secret.authenticate(&self.salt_enc);
         //println!("{}", StrBuf::bufh("salt_auth", &self.salt_auth));
 
         Ok(())
     }
+
+    /// hmac-secret / hmac-secret-mc extension input
+    /// - keyAgreement(0x01), saltEnc(0x02), saltAuth(0x03), pinUvAuthProtocol(0x04)
+    pub fn to_value(&self) -> Value {
+        let mut param = BTreeMap::new();
+
+        // keyAgreement(0x01)
+        let val = self.shared_secret.public_key.to_value().unwrap();
+        param.insert(Value::Integer(0x01), val);
+
+        // saltEnc(0x02)
+        param.insert(Value::Integer(0x02), Value::Bytes(self.salt_enc.to_vec()));
+
+        // saltAuth(0x03)
+        param.insert(Value::Integer(0x03), Value::Bytes(self.salt_auth.to_vec()));
+
+        // pinUvAuthProtocol(0x04) : optional if 1
+        let pin_uv_auth_protocol = self.shared_secret.pin_uv_auth_protocol;
+        if pin_uv_auth_protocol != 1 {
+            param.insert(
+                Value::Integer(0x04),
+                Value::Integer(pin_uv_auth_protocol as i128),
+            );
+        }
+
+        Value::Map(param)
+    }
+
+    /// Decrypt the hmac-secret / hmac-secret-mc extension output
+    /// - one salt : (output1, None)
+    /// - two salts : (output1, Some(output2))
+    pub fn decrypt_output(
+        &self,
+        output_enc: &[u8],
+    ) -> Result<([u8; 32], Option<[u8; 32]>), String> {
+        let output = Zeroizing::new(self.shared_secret.decrypt_message(output_enc)?);
+        let to_32 = |x: &[u8]| {
+            let mut ret = [0u8; 32];
+            ret.copy_from_slice(x);
+            ret
+        };
+        match output.len() {
+            32 => Ok((to_32(&output), None)),
+            64 => Ok((to_32(&output[0..32]), Some(to_32(&output[32..64])))),
+            _ => Err("hmac-secret output length error".to_string()),
+        }
+    }
 }
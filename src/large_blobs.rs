@@ -0,0 +1,126 @@
+use crate::client_pin;
+use crate::client_pin_command::Permission;
+use crate::ctaphid;
+use crate::get_info;
+use crate::get_info_params::Info;
+use crate::large_blobs_command;
+use crate::large_blobs_params::LargeBlobArray;
+use crate::large_blobs_response;
+use crate::util;
+use crate::FidoKeyHid;
+
+use anyhow::{anyhow, Error, Result};
+
+// maxFragmentLength = maxMsgSize - 64
+fn max_fragment_length(info: &Info) -> Result<u32> {
+    let max_msg_size = if info.max_msg_size > 0 {
+        info.max_msg_size as u32
+    } else {
+        1024
+    };
+    match max_msg_size.saturating_sub(64) {
+        0 => Err(anyhow!(
+            "maxMsgSize is too small for largeBlobs. ({})",
+            max_msg_size
+        )),
+        len => Ok(len),
+    }
+}
+
+fn check_support(info: &Info) -> Result<()> {
+    if info.option("largeBlobs") == Some(true) {
+        Ok(())
+    } else {
+        Err(anyhow!("largeBlobs is not supported."))
+    }
+}
+
+pub fn get_large_blob_array(device: &FidoKeyHid) -> Result<LargeBlobArray> {
+    let info = get_info::get_info(device)?;
+    check_support(&info)?;
+    let fragment_length = max_fragment_length(&info)?;
+
+    let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+
+    let mut serialized = vec![];
+    loop {
+        let send_payload =
+            large_blobs_command::create_payload_get(serialized.len() as u32, fragment_length);
+        let response_cbor =
+            ctaphid::ctaphid_cbor(device, &cid, &send_payload).map_err(Error::msg)?;
+        let mut fragment = large_blobs_response::parse_cbor(&response_cbor).map_err(Error::msg)?;
+
+        let is_last = (fragment.len() as u32) < fragment_length;
+        serialized.append(&mut fragment);
+        if is_last {
+            break;
+        }
+    }
+
+    if device.enable_log {
+        println!("large-blob array = {}", util::to_hex_str(&serialized));
+    }
+
+    // If the integrity check fails, the large-blob array is treated as empty.
+    match LargeBlobArray::parse(&serialized) {
+        Ok(array) => Ok(array),
+        Err(err) => {
+            if device.enable_log {
+                println!("{}", err);
+            }
+            Ok(LargeBlobArray::default())
+        }
+    }
+}
+
+pub fn set_large_blob_array(
+    device: &FidoKeyHid,
+    pin: Option<&str>,
+    array: &LargeBlobArray,
+) -> Result<()> {
+    let info = get_info::get_info(device)?;
+    check_support(&info)?;
+    let fragment_length = max_fragment_length(&info)? as usize;
+
+    let serialized = array.serialize();
+    if info.max_serialized_large_blob_array > 0
+        && serialized.len() > info.max_serialized_large_blob_array as usize
+    {
+        return Err(anyhow!(
+            "large-blob array is too large. ({} > maxSerializedLargeBlobArray:{})",
+            serialized.len(),
+            info.max_serialized_large_blob_array
+        ));
+    }
+
+    let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+
+    // pin token
+    let pin_token = {
+        if let Some(pin) = pin {
+            Some(client_pin::get_pinuv_auth_token_with_permission(
+                device,
+                &cid,
+                pin,
+                &[Permission::Lbw],
+            )?)
+        } else {
+            None
+        }
+    };
+
+    for (i, fragment) in serialized.chunks(fragment_length).enumerate() {
+        let offset = (i * fragment_length) as u32;
+        let length = if offset == 0 {
+            Some(serialized.len() as u32)
+        } else {
+            None
+        };
+
+        let send_payload =
+            large_blobs_command::create_payload_set(pin_token.as_ref(), offset, length, fragment);
+        ctaphid::ctaphid_cbor(device, &cid, &send_payload).map_err(Error::msg)?;
+    }
+
+    Ok(())
+}
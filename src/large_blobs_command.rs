@@ -0,0 +1,68 @@
+use crate::ctapdef;
+use crate::pintoken::PinToken;
+use ring::digest;
+use serde_cbor::to_vec;
+use serde_cbor::Value;
+use std::collections::BTreeMap;
+
+pub fn create_payload_get(offset: u32, get: u32) -> Vec<u8> {
+    let mut map = BTreeMap::new();
+
+    // get(0x01) : the number of bytes requested to read
+    map.insert(Value::Integer(0x01), Value::Integer(get as i128));
+
+    // offset(0x03) : the byte offset at which to read/write
+    map.insert(Value::Integer(0x03), Value::Integer(offset as i128));
+
+    to_payload(map)
+}
+
+pub fn create_payload_set(
+    pin_token: Option<&PinToken>,
+    offset: u32,
+    length: Option<u32>,
+    set: &[u8],
+) -> Vec<u8> {
+    let mut map = BTreeMap::new();
+
+    // set(0x02) : a fragment to write
+    map.insert(Value::Integer(0x02), Value::Bytes(set.to_vec()));
+
+    // offset(0x03)
+    map.insert(Value::Integer(0x03), Value::Integer(offset as i128));
+
+    // length(0x04) : the total length of a write operation (only offset == 0)
+    if let Some(length) = length {
+        map.insert(Value::Integer(0x04), Value::Integer(length as i128));
+    }
+
+    if let Some(pin_token) = pin_token {
+        // pinUvAuthParam(0x05)
+        // - authenticate(pinUvAuthToken, 32×0xff || h’0c00' || uint32LittleEndian(offset) || SHA-256(contents of set byte string))
+        let pin_uv_auth_param = {
+            let mut message = vec![0xff; 32];
+            message.append(&mut vec![ctapdef::AUTHENTICATOR_LARGE_BLOBS, 0x00]);
+            message.append(&mut offset.to_le_bytes().to_vec());
+            let hash = digest::digest(&digest::SHA256, set);
+            message.append(&mut hash.as_ref().to_vec());
+
+            pin_token.authenticate(&message)
+        };
+        map.insert(Value::Integer(0x05), Value::Bytes(pin_uv_auth_param));
+
+        // pinUvAuthProtocol(0x06)
+        map.insert(
+            Value::Integer(0x06),
+            Value::Integer(pin_token.pin_uv_auth_protocol as i128),
+        );
+    }
+
+    to_payload(map)
+}
+
+fn to_payload(map: BTreeMap<Value, Value>) -> Vec<u8> {
+    let cbor = Value::Map(map);
+    let mut payload = [ctapdef::AUTHENTICATOR_LARGE_BLOBS].to_vec();
+    payload.append(&mut to_vec(&cbor).unwrap());
+    payload
+}
@@ -0,0 +1,220 @@
+/*!
+large_blobs API parameters
+*/
+
+use crate::enc_aes256_gcm;
+use crate::str_buf::StrBuf;
+use crate::util;
+use flate2::read::DeflateDecoder;
+use flate2::write::DeflateEncoder;
+use flate2::Compression;
+use ring::digest;
+use ring::rand::SecureRandom;
+use serde_cbor::Value;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+use std::io::{Read, Write};
+
+// LEFT(SHA-256(CBOR array), 16)
+const TRAILER_SIZE: usize = 16;
+
+/// Large-blob array
+/// [6.10.3. Large, per-credential blobs](https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-20210615.html#large-blob)
+#[derive(Debug, Default, Clone)]
+pub struct LargeBlobArray {
+    pub entries: Vec<LargeBlobEntry>,
+    // elements that are not large-blob maps are kept as they are
+    others: Vec<Value>,
+}
+
+impl LargeBlobArray {
+    /// Parse the serialized large-blob array (CBOR array || LEFT(SHA-256(CBOR array), 16))
+    pub fn parse(serialized: &[u8]) -> Result<LargeBlobArray, String> {
+        if serialized.len() < 1 + TRAILER_SIZE {
+            return Err("large-blob array is too short".to_string());
+        }
+
+        let (array, trailer) = serialized.split_at(serialized.len() - TRAILER_SIZE);
+        let hash = digest::digest(&digest::SHA256, array);
+        if hash.as_ref()[0..TRAILER_SIZE] != *trailer {
+            return Err("large-blob array integrity check failed".to_string());
+        }
+
+        let mut ret = LargeBlobArray::default();
+        let cbor: Value = serde_cbor::from_slice(array).map_err(|_| "parse error 1".to_string())?;
+        if let Value::Array(xs) = cbor {
+            for x in xs {
+                match LargeBlobEntry::new(&x) {
+                    Ok(entry) => ret.entries.push(entry),
+                    Err(_) => ret.others.push(x),
+                }
+            }
+            Ok(ret)
+        } else {
+            Err("Cast Error : Value is not Array.".to_string())
+        }
+    }
+
+    /// Create the serialized large-blob array
+    pub fn serialize(&self) -> Vec<u8> {
+        let mut xs: Vec<Value> = self.entries.iter().map(|x| x.to_value()).collect();
+        xs.append(&mut self.others.to_vec());
+
+        let mut serialized = serde_cbor::to_vec(&Value::Array(xs)).unwrap();
+        let hash = digest::digest(&digest::SHA256, &serialized);
+        serialized.append(&mut hash.as_ref()[0..TRAILER_SIZE].to_vec());
+        serialized
+    }
+
+    /// Get the data of the entry that can be decrypted with large_blob_key
+    pub fn find(&self, large_blob_key: &[u8]) -> Option<Vec<u8>> {
+        self.entries
+            .iter()
+            .find_map(|x| x.decrypt(large_blob_key).ok())
+    }
+
+    /// Replace the entry of large_blob_key with data
+    pub fn put(&mut self, large_blob_key: &[u8], data: &[u8]) -> Result<(), String> {
+        let entry = LargeBlobEntry::encrypt(large_blob_key, data)?;
+        self.remove(large_blob_key);
+        self.entries.push(entry);
+        Ok(())
+    }
+
+    /// Remove the entries that can be decrypted with large_blob_key
+    pub fn remove(&mut self, large_blob_key: &[u8]) -> bool {
+        let count = self.entries.len();
+        self.entries.retain(|x| x.decrypt(large_blob_key).is_err());
+        count != self.entries.len()
+    }
+}
+
+impl fmt::Display for LargeBlobArray {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut strbuf = StrBuf::new(18);
+        strbuf.append("- entries", &self.entries.len());
+        for entry in &self.entries {
+            strbuf.add(&format!("{}", entry));
+        }
+        strbuf.append("- others", &self.others.len());
+        write!(f, "{}", strbuf.build())
+    }
+}
+
+/// Large-blob map (one element of the large-blob array)
+#[derive(Debug, Default, Clone)]
+pub struct LargeBlobEntry {
+    /// AEAD_AES_256_GCM ciphertext (with tag)
+    pub ciphertext: Vec<u8>,
+    pub nonce: [u8; 12],
+    /// Size of the original data before compression
+    pub orig_size: u64,
+}
+
+impl LargeBlobEntry {
+    pub(crate) fn new(cbor: &Value) -> Result<LargeBlobEntry, String> {
+        let mut entry = LargeBlobEntry::default();
+        let mut nonce = vec![];
+        if let Value::Map(xs) = cbor {
+            for (key, val) in xs {
+                if let Value::Integer(member) = key {
+                    match member {
+                        0x01 => entry.ciphertext = util::cbor_value_to_vec_u8(val)?,
+                        0x02 => nonce = util::cbor_value_to_vec_u8(val)?,
+                        0x03 => entry.orig_size = util::cbor_value_to_num(val)?,
+                        _ => {}
+                    }
+                }
+            }
+        } else {
+            return Err("Cast Error : Value is not a Map.".to_string());
+        }
+
+        entry.nonce = <[u8; 12]>::try_from(nonce.as_slice())
+            .map_err(|_| "large-blob nonce error".to_string())?;
+        if entry.ciphertext.len() < TRAILER_SIZE {
+            return Err("large-blob ciphertext error".to_string());
+        }
+        Ok(entry)
+    }
+
+    pub(crate) fn to_value(&self) -> Value {
+        let mut map = BTreeMap::new();
+        map.insert(Value::Integer(0x01), Value::Bytes(self.ciphertext.to_vec()));
+        map.insert(Value::Integer(0x02), Value::Bytes(self.nonce.to_vec()));
+        map.insert(Value::Integer(0x03), Value::Integer(self.orig_size as i128));
+        Value::Map(map)
+    }
+
+    /// Create an entry - encrypt(largeBlobKey, DEFLATE(orig_data))
+    pub fn encrypt(large_blob_key: &[u8], orig_data: &[u8]) -> Result<LargeBlobEntry, String> {
+        let key = to_key(large_blob_key)?;
+
+        let mut nonce = [0u8; 12];
+        ring::rand::SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| "random error".to_string())?;
+
+        let compressed = {
+            let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+            encoder.write_all(orig_data).map_err(|e| e.to_string())?;
+            encoder.finish().map_err(|e| e.to_string())?
+        };
+
+        let orig_size = orig_data.len() as u64;
+        let ciphertext =
+            enc_aes256_gcm::encrypt_message(&key, &nonce, &create_aad(orig_size), &compressed)?;
+
+        Ok(LargeBlobEntry {
+            ciphertext,
+            nonce,
+            orig_size,
+        })
+    }
+
+    /// Get the original data - INFLATE(decrypt(largeBlobKey, ciphertext))
+    pub fn decrypt(&self, large_blob_key: &[u8]) -> Result<Vec<u8>, String> {
+        let key = to_key(large_blob_key)?;
+
+        let compressed = enc_aes256_gcm::decrypt_message(
+            &key,
+            &self.nonce,
+            &create_aad(self.orig_size),
+            &self.ciphertext,
+        )?;
+
+        let mut orig_data = vec![];
+        DeflateDecoder::new(compressed.as_slice())
+            .take(self.orig_size + 1)
+            .read_to_end(&mut orig_data)
+            .map_err(|e| e.to_string())?;
+
+        if orig_data.len() as u64 != self.orig_size {
+            return Err("large-blob origSize mismatch".to_string());
+        }
+        Ok(orig_data)
+    }
+}
+
+impl fmt::Display for LargeBlobEntry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut strbuf = StrBuf::new(18);
+        strbuf
+            .appenh("  - ciphertext", &self.ciphertext)
+            .appenh("  - nonce", &self.nonce)
+            .append("  - orig_size", &self.orig_size);
+        write!(f, "{}", strbuf.build())
+    }
+}
+
+fn to_key(large_blob_key: &[u8]) -> Result<[u8; 32], String> {
+    <[u8; 32]>::try_from(large_blob_key).map_err(|_| "largeBlobKey must be 32 bytes".to_string())
+}
+
+// associated data = "blob" || uint64LittleEndian(origSize)
+fn create_aad(orig_size: u64) -> Vec<u8> {
+    let mut aad = b"blob".to_vec();
+    aad.append(&mut orig_size.to_le_bytes().to_vec());
+    aad
+}
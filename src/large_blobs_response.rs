@@ -0,0 +1,16 @@
+use crate::util;
+use serde_cbor::Value;
+
+pub fn parse_cbor(bytes: &[u8]) -> Result<Vec<u8>, String> {
+    let maps = util::cbor_bytes_to_map(bytes)?;
+    for (key, val) in &maps {
+        if let Value::Integer(member) = key {
+            match member {
+                // config(0x01) : a fragment of the serialized large-blob array
+                0x01 => return util::cbor_value_to_vec_u8(val),
+                _ => println!("parse_cbor_member - unknown member {:?}", member),
+            }
+        }
+    }
+    Ok(vec![])
+}
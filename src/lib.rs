@@ -12,6 +12,7 @@ pub mod bio_enrollment_params;
 mod bio_enrollment_response;
 mod client_pin;
 mod client_pin_command;
+pub mod client_pin_params;
 mod client_pin_response;
 mod config_command;
 mod cose;
@@ -23,6 +24,7 @@ mod ctapdef;
 mod ctaphid;
 mod ctapihd_nitro;
 pub mod enc_aes256_cbc;
+pub mod enc_aes256_gcm;
 pub mod enc_hmac_sha_256;
 mod get_assertion;
 mod get_assertion_command;
@@ -34,20 +36,32 @@ pub mod get_info_params;
 mod get_info_response;
 mod get_next_assertion_command;
 mod hmac;
+mod large_blobs;
+mod large_blobs_command;
+pub mod large_blobs_params;
+mod large_blobs_response;
 mod make_credential;
 mod make_credential_command;
 pub mod make_credential_params;
 mod make_credential_response;
 pub mod nitrokey;
 mod p256;
+mod paging;
+pub mod pin_provider;
 mod pintoken;
+pub mod prf_params;
 pub mod public_key;
 pub mod public_key_credential_descriptor;
+pub mod public_key_credential_parameters;
 pub mod public_key_credential_rp_entity;
 pub mod public_key_credential_user_entity;
 mod selection_command;
 mod ss;
 pub mod str_buf;
+mod u2f;
+mod u2f_command;
+pub mod u2f_params;
+mod u2f_response;
 pub mod util;
 pub mod verifier;
 
@@ -55,14 +69,24 @@ pub mod verifier;
 use crate::bio_enrollment_command::SubCommand as BioCmd;
 use crate::bio_enrollment_params::{BioSensorInfo, EnrollStatus1, EnrollStatus2, TemplateInfo};
 use crate::client_pin_command::SubCommand as PinCmd;
+use crate::client_pin_params::UserVerification;
 use crate::get_assertion_params::Assertion;
+use crate::get_assertion_params::AssertionIter;
 use crate::get_assertion_params::Extension as Gext;
+use crate::get_assertion_params::NoCredentials;
+use crate::large_blobs_params::LargeBlobArray;
 use crate::make_credential_params::Attestation;
 use crate::make_credential_params::CredentialSupportedKeyType;
+use crate::make_credential_params::EnterpriseAttestation;
 use crate::make_credential_params::Extension as Mext;
+use crate::pin_provider::{PinProvider, PinReason};
+use crate::prf_params::{PrfInputs, PrfOutputs};
 use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
 use crate::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
+use crate::u2f_params::{AuthenticateControl, AuthenticateResponse, RegisterResponse};
 use anyhow::{anyhow, Error, Result};
+use std::fmt;
+use zeroize::Zeroizing;
 
 #[cfg(not(target_os = "linux"))]
 mod fidokey;
@@ -89,8 +113,18 @@ pub type Cfg = LibCfg;
 pub struct LibCfg {
     pub hid_params: Vec<HidParam>,
     pub enable_log: bool,
-    pub use_pre_bio_enrollment: bool,
-    pub use_pre_credential_management: bool,
+    /// - None : decided from authenticatorGetInfo (default)
+    /// - Some(true) : authenticatorBioEnrollment (0x40) of FIDO_2_1_PRE
+    /// - Some(false) : authenticatorBioEnrollment (0x09) of FIDO_2_1
+    pub use_pre_bio_enrollment: Option<bool>,
+    /// - None : decided from authenticatorGetInfo (default)
+    /// - Some(true) : authenticatorCredentialManagement (0x41) of FIDO_2_1_PRE
+    /// - Some(false) : authenticatorCredentialManagement (0x0A) of FIDO_2_1
+    pub use_pre_credential_management: Option<bool>,
+    /// PIN retry guard (opt-in)
+    /// - Some(floor) : the PIN is not sent if the PIN retries are at or below floor
+    ///   or the authenticator must be power cycled (client_pin_params::PinRetryError)
+    pub pin_retry_floor: Option<u32>,
     pub keep_alive_msg: String,
 }
 impl LibCfg {
@@ -98,8 +132,9 @@ impl LibCfg {
         LibCfg {
             hid_params: HidParam::auto(),
             enable_log: false,
-            use_pre_bio_enrollment: true,
-            use_pre_credential_management: true,
+            use_pre_bio_enrollment: None,
+            use_pre_credential_management: None,
+            pin_retry_floor: None,
             keep_alive_msg: "- Touch the sensor on the authenticator".to_string(),
         }
     }
@@ -230,6 +265,35 @@ pub fn get_info_u2f(cfg: &LibCfg) -> Result<String> {
     get_info::get_info_u2f(&device)
 }
 
+/// U2F(CTAP1) - Registration
+/// - app_param : SHA-256 of the application identity (32 bytes)
+/// - challenge : SHA-256 of the client data (32 bytes)
+/// - waits for the user to touch the key
+pub fn u2f_register(cfg: &LibCfg, app_param: &[u8], challenge: &[u8]) -> Result<RegisterResponse> {
+    let device = get_device(cfg)?;
+    u2f::register(&device, app_param, challenge)
+}
+
+/// U2F(CTAP1) - Authentication (check-only)
+/// - returns whether key_handle was created by this key for app_param, without user presence
+pub fn u2f_check_only(cfg: &LibCfg, app_param: &[u8], key_handle: &[u8]) -> Result<bool> {
+    let device = get_device(cfg)?;
+    u2f::check_only(&device, app_param, key_handle)
+}
+
+/// U2F(CTAP1) - Authentication
+/// - EnforceUserPresence waits for the user to touch the key
+pub fn u2f_authenticate(
+    cfg: &LibCfg,
+    control: AuthenticateControl,
+    app_param: &[u8],
+    challenge: &[u8],
+    key_handle: &[u8],
+) -> Result<AuthenticateResponse> {
+    let device = get_device(cfg)?;
+    u2f::authenticate(&device, control, app_param, challenge, key_handle)
+}
+
 /// Get UV retry count
 pub fn get_uv_retries(cfg: &LibCfg) -> Result<i32> {
     let device = get_device(cfg)?;
@@ -263,21 +327,59 @@ pub fn get_pin_retries(cfg: &LibCfg) -> Result<i32> {
 }
 
 /// Set New PIN
+/// - The PIN is checked against the PIN policy of the authenticator before it is sent.
 pub fn set_new_pin(cfg: &LibCfg, pin: &str) -> Result<()> {
     let device = get_device(cfg)?;
+    let info = get_info::get_info(&device)?;
+    client_pin::check_pin_policy(&info, pin, None)?;
+
     let cid = ctaphid::ctaphid_init(&device).map_err(Error::msg)?;
     client_pin::set_pin(&device, &cid, pin)?;
     Ok(())
 }
 
 /// Change PIN
+/// - The new PIN is checked against the PIN policy of the authenticator before it is sent.
 pub fn change_pin(cfg: &LibCfg, current_pin: &str, new_pin: &str) -> Result<()> {
     let device = get_device(cfg)?;
+    let info = get_info::get_info(&device)?;
+    client_pin::check_pin_policy(&info, new_pin, Some(current_pin))?;
+
     let cid = ctaphid::ctaphid_init(&device).map_err(Error::msg)?;
     client_pin::change_pin(&device, &cid, current_pin, new_pin)?;
     Ok(())
 }
 
+/// Set New PIN - the new PIN is asked for with PinReason::Set
+pub fn set_new_pin_with_provider(cfg: &LibCfg, pin_provider: &dyn PinProvider) -> Result<()> {
+    let device = get_device(cfg)?;
+    let info = get_info::get_info(&device)?;
+    let pin = client_pin::provide_pin(&device, &info, pin_provider, PinReason::Set, None)?;
+
+    let cid = ctaphid::ctaphid_init(&device).map_err(Error::msg)?;
+    client_pin::set_pin(&device, &cid, &pin)?;
+    Ok(())
+}
+
+/// Change PIN - the current PIN is asked for with PinReason::Verify, the new PIN with PinReason::Change
+pub fn change_pin_with_provider(cfg: &LibCfg, pin_provider: &dyn PinProvider) -> Result<()> {
+    let device = get_device(cfg)?;
+    let info = get_info::get_info(&device)?;
+    let current_pin =
+        client_pin::provide_pin(&device, &info, pin_provider, PinReason::Verify, None)?;
+    let new_pin = client_pin::provide_pin(
+        &device,
+        &info,
+        pin_provider,
+        PinReason::Change,
+        Some(&current_pin),
+    )?;
+
+    let cid = ctaphid::ctaphid_init(&device).map_err(Error::msg)?;
+    client_pin::change_pin(&device, &cid, &current_pin, &new_pin)?;
+    Ok(())
+}
+
 fn should_uv(pin: Option<&str>) -> Option<bool> {
     match pin {
         Some(_) => None,
@@ -285,6 +387,15 @@ fn should_uv(pin: Option<&str>) -> Option<bool> {
     }
 }
 
+// allowList of a single credential - empty for discoverable credentials
+fn allow_list_of(credential_id: &[u8]) -> Vec<PublicKeyCredentialDescriptor> {
+    if credential_id.is_empty() {
+        vec![]
+    } else {
+        vec![PublicKeyCredentialDescriptor::new(credential_id)]
+    }
+}
+
 /// Registration command.Generate credentials(with PIN,non Resident Key)
 pub fn make_credential(
     cfg: &LibCfg,
@@ -295,14 +406,13 @@ pub fn make_credential(
     let device = get_device(cfg)?;
     make_credential::make_credential(
         &device,
-        rpid,
-        challenge,
-        pin,
-        false,
-        None,
-        should_uv(pin),
-        None,
-        None,
+        &make_credential::Params {
+            rpid,
+            challenge,
+            pin,
+            uv: should_uv(pin),
+            ..Default::default()
+        },
     )
 }
 
@@ -318,14 +428,14 @@ pub fn make_credential_with_key_type(
     let device = get_device(cfg)?;
     make_credential::make_credential(
         &device,
-        rpid,
-        challenge,
-        pin,
-        false,
-        None,
-        should_uv(pin),
-        None,
-        key_type,
+        &make_credential::Params {
+            rpid,
+            challenge,
+            pin,
+            uv: should_uv(pin),
+            key_types: &key_type.into_iter().collect::<Vec<_>>(),
+            ..Default::default()
+        },
     )
 }
 
@@ -339,14 +449,14 @@ pub fn make_credential_with_extensions(
     let device = get_device(cfg)?;
     make_credential::make_credential(
         &device,
-        rpid,
-        challenge,
-        pin,
-        false,
-        None,
-        should_uv(pin),
-        extensions,
-        None,
+        &make_credential::Params {
+            rpid,
+            challenge,
+            pin,
+            uv: should_uv(pin),
+            extensions,
+            ..Default::default()
+        },
     )
 }
 
@@ -361,43 +471,68 @@ pub fn make_credential_rk(
     let device = get_device(cfg)?;
     make_credential::make_credential(
         &device,
-        rpid,
-        challenge,
-        pin,
-        true,
-        Some(rkparam),
-        should_uv(pin),
-        None,
-        None,
+        &make_credential::Params {
+            rpid,
+            challenge,
+            pin,
+            rkparam: Some(rkparam),
+            uv: should_uv(pin),
+            ..Default::default()
+        },
     )
 }
 
 pub fn make_credential_with_args(cfg: &LibCfg, args: &MakeCredentialArgs) -> Result<Attestation> {
     let device = get_device(cfg)?;
 
-    let extensions = if args.extensions.is_some() {
-        Some(args.extensions.as_ref().unwrap())
+    // prf → hmac-secret / hmac-secret-mc
+    // - without hmac-secret-mc, prf is only enabled and the results are left empty
+    let mut prf_extensions = args.extensions.clone().unwrap_or_default();
+    if let Some(prf) = &args.prf {
+        let hmac_secret_mc = prf.eval.is_some() && {
+            let info = get_info::get_info(&device)?;
+            let ext = Mext::HmacSecretMc((None, None)).to_string();
+            info.extensions.contains(&ext)
+        };
+        prf_extensions.append(
+            &mut prf
+                .to_make_credential_extensions(hmac_secret_mc)
+                .map_err(Error::msg)?,
+        );
+    }
+
+    let extensions = if args.extensions.is_some() || args.prf.is_some() {
+        Some(&prf_extensions)
     } else {
         None
     };
 
-    let (rk, rk_param) = if args.rkparam.is_some() {
-        (true, Some(args.rkparam.as_ref().unwrap()))
-    } else {
-        (false, None)
-    };
+    let rk = args.rkparam.is_some();
 
-    make_credential::make_credential(
+    let (provided_pin, user_verification) =
+        provide_pin(&device, args.pin_provider, args.user_verification, Some(rk))?;
+
+    let mut att = make_credential::make_credential(
         &device,
-        &args.rpid,
-        &args.challenge,
-        args.pin,
-        rk,
-        rk_param,
-        args.uv,
-        extensions,
-        args.key_type,
-    )
+        &make_credential::Params {
+            rpid: &args.rpid,
+            challenge: &args.challenge,
+            pin: provided_pin.as_ref().map(|pin| pin.as_str()).or(args.pin),
+            rkparam: args.rkparam.as_ref(),
+            uv: args.uv,
+            user_verification,
+            extensions,
+            key_types: &args.key_types,
+            exclude_list: &args.exclude_list,
+            enterprise_attestation: args.enterprise_attestation,
+        },
+    )?;
+
+    if args.prf.is_some() {
+        att.prf = Some(PrfOutputs::from_make_credential_extensions(&att.extensions));
+    }
+
+    Ok(att)
 }
 
 #[derive(Debug)]
@@ -405,10 +540,15 @@ pub struct MakeCredentialArgs<'a> {
     rpid: String,
     challenge: Vec<u8>,
     pin: Option<&'a str>,
-    key_type: Option<CredentialSupportedKeyType>,
+    pin_provider: Option<PinProviderRef<'a>>,
+    key_types: Vec<CredentialSupportedKeyType>,
     uv: Option<bool>,
+    user_verification: Option<UserVerification>,
     rkparam: Option<PublicKeyCredentialUserEntity>,
     extensions: Option<Vec<Mext>>,
+    prf: Option<PrfInputs>,
+    exclude_list: Vec<PublicKeyCredentialDescriptor>,
+    enterprise_attestation: Option<EnterpriseAttestation>,
 }
 impl<'a> MakeCredentialArgs<'a> {
     pub fn builder() -> MakeCredentialArgsBuilder<'a> {
@@ -421,10 +561,15 @@ pub struct MakeCredentialArgsBuilder<'a> {
     rpid: String,
     challenge: Vec<u8>,
     pin: Option<&'a str>,
-    key_type: Option<CredentialSupportedKeyType>,
+    pin_provider: Option<PinProviderRef<'a>>,
+    key_types: Vec<CredentialSupportedKeyType>,
     uv: Option<bool>,
+    user_verification: Option<UserVerification>,
     rkparam: Option<PublicKeyCredentialUserEntity>,
     extensions: Option<Vec<Mext>>,
+    prf: Option<PrfInputs>,
+    exclude_list: Vec<PublicKeyCredentialDescriptor>,
+    enterprise_attestation: Option<EnterpriseAttestation>,
 }
 impl<'a> MakeCredentialArgsBuilder<'a> {
     pub fn new(rpid: &str, challenge: &[u8]) -> MakeCredentialArgsBuilder<'a> {
@@ -448,11 +593,42 @@ impl<'a> MakeCredentialArgsBuilder<'a> {
         self
     }
 
+    /// The PIN is asked for only if the user verification needs it
+    /// (with UserVerification::Preferred unless a policy is set).
+    pub fn pin_provider(
+        mut self,
+        pin_provider: &'a dyn PinProvider,
+    ) -> MakeCredentialArgsBuilder<'a> {
+        self.pin_provider = Some(PinProviderRef(pin_provider));
+        self
+    }
+
+    /// User verification policy resolved against authenticatorGetInfo.
+    /// The PIN (if any) is used only when the policy calls for user verification.
+    /// The method actually used is reported in user_verification_method of the result.
+    pub fn user_verification(
+        mut self,
+        user_verification: UserVerification,
+    ) -> MakeCredentialArgsBuilder<'a> {
+        self.user_verification = Some(user_verification);
+        self
+    }
+
     pub fn key_type(
         mut self,
         key_type: CredentialSupportedKeyType,
     ) -> MakeCredentialArgsBuilder<'a> {
-        self.key_type = Some(key_type);
+        self.key_types = vec![key_type];
+        self
+    }
+
+    /// pubKeyCredParams in order of preference.
+    /// Algorithms not supported by the authenticator are removed before sending.
+    pub fn key_types(
+        mut self,
+        key_types: &[CredentialSupportedKeyType],
+    ) -> MakeCredentialArgsBuilder<'a> {
+        self.key_types = key_types.to_vec();
         self
     }
 
@@ -469,15 +645,45 @@ impl<'a> MakeCredentialArgsBuilder<'a> {
         self
     }
 
+    /// excludeList - fails with make_credential_params::CredentialExcluded
+    /// if one of the credentials already exists on the authenticator
+    pub fn exclude_list(
+        mut self,
+        exclude_list: &[PublicKeyCredentialDescriptor],
+    ) -> MakeCredentialArgsBuilder<'a> {
+        self.exclude_list = exclude_list.to_vec();
+        self
+    }
+
+    /// enterpriseAttestation - requires the ep option to be enabled on the authenticator
+    pub fn enterprise_attestation(
+        mut self,
+        enterprise_attestation: EnterpriseAttestation,
+    ) -> MakeCredentialArgsBuilder<'a> {
+        self.enterprise_attestation = Some(enterprise_attestation);
+        self
+    }
+
+    /// WebAuthn prf extension (eval is evaluated with hmac-secret-mc)
+    pub fn prf(mut self, prf: &PrfInputs) -> MakeCredentialArgsBuilder<'a> {
+        self.prf = Some(prf.clone());
+        self
+    }
+
     pub fn build(self) -> MakeCredentialArgs<'a> {
         MakeCredentialArgs {
             rpid: self.rpid,
             challenge: self.challenge,
             pin: self.pin,
-            key_type: self.key_type,
+            pin_provider: self.pin_provider,
+            key_types: self.key_types,
             uv: self.uv,
+            user_verification: self.user_verification,
             rkparam: self.rkparam,
             extensions: self.extensions,
+            prf: self.prf,
+            exclude_list: self.exclude_list,
+            enterprise_attestation: self.enterprise_attestation,
         }
     }
 }
@@ -494,13 +700,15 @@ pub fn get_assertion(
 
     let asss = get_assertion::get_assertion(
         &device,
-        rpid,
-        challenge,
-        credential_id,
-        pin,
-        true,
-        should_uv(pin),
-        None,
+        &get_assertion::Params {
+            rpid,
+            challenge,
+            allow_list: &allow_list_of(credential_id),
+            pin,
+            up: true,
+            uv: should_uv(pin),
+            ..Default::default()
+        },
     )?;
     Ok(asss[0].clone())
 }
@@ -517,13 +725,16 @@ pub fn get_assertion_with_extensios(
     let device = get_device(cfg)?;
     let asss = get_assertion::get_assertion(
         &device,
-        rpid,
-        challenge,
-        credential_id,
-        pin,
-        true,
-        should_uv(pin),
-        extensions,
+        &get_assertion::Params {
+            rpid,
+            challenge,
+            allow_list: &allow_list_of(credential_id),
+            pin,
+            up: true,
+            uv: should_uv(pin),
+            extensions,
+            ..Default::default()
+        },
     )?;
     Ok(asss[0].clone())
 }
@@ -536,16 +747,16 @@ pub fn get_assertions_rk(
     pin: Option<&str>,
 ) -> Result<Vec<Assertion>> {
     let device = get_device(cfg)?;
-    let dmy: [u8; 0] = [];
     get_assertion::get_assertion(
         &device,
-        rpid,
-        challenge,
-        &dmy,
-        pin,
-        true,
-        should_uv(pin),
-        None,
+        &get_assertion::Params {
+            rpid,
+            challenge,
+            pin,
+            up: true,
+            uv: should_uv(pin),
+            ..Default::default()
+        },
     )
 }
 
@@ -554,9 +765,13 @@ pub struct GetAssertionArgs<'a> {
     rpid: String,
     challenge: Vec<u8>,
     pin: Option<&'a str>,
-    credential_id: Option<Vec<u8>>,
+    pin_provider: Option<PinProviderRef<'a>>,
+    allow_list: Vec<PublicKeyCredentialDescriptor>,
+    up: Option<bool>,
     uv: Option<bool>,
+    user_verification: Option<UserVerification>,
     extensions: Option<Vec<Gext>>,
+    prf: Option<PrfInputs>,
 }
 impl<'a> GetAssertionArgs<'a> {
     pub fn builder() -> GetAssertionArgsBuilder<'a> {
@@ -569,9 +784,13 @@ pub struct GetAssertionArgsBuilder<'a> {
     rpid: String,
     challenge: Vec<u8>,
     pin: Option<&'a str>,
-    credential_id: Option<Vec<u8>>,
+    pin_provider: Option<PinProviderRef<'a>>,
+    allow_list: Vec<PublicKeyCredentialDescriptor>,
+    up: Option<bool>,
     uv: Option<bool>,
+    user_verification: Option<UserVerification>,
     extensions: Option<Vec<Gext>>,
+    prf: Option<PrfInputs>,
 }
 impl<'a> GetAssertionArgsBuilder<'a> {
     pub fn new(rpid: &str, challenge: &[u8]) -> GetAssertionArgsBuilder<'a> {
@@ -595,13 +814,58 @@ impl<'a> GetAssertionArgsBuilder<'a> {
         self
     }
 
+    /// The PIN is asked for only if the user verification needs it
+    /// (with UserVerification::Preferred unless a policy is set).
+    pub fn pin_provider(
+        mut self,
+        pin_provider: &'a dyn PinProvider,
+    ) -> GetAssertionArgsBuilder<'a> {
+        self.pin_provider = Some(PinProviderRef(pin_provider));
+        self
+    }
+
+    /// User verification policy resolved against authenticatorGetInfo.
+    /// The PIN (if any) is used only when the policy calls for user verification.
+    /// The method actually used is reported in user_verification_method of the result.
+    pub fn user_verification(
+        mut self,
+        user_verification: UserVerification,
+    ) -> GetAssertionArgsBuilder<'a> {
+        self.user_verification = Some(user_verification);
+        self
+    }
+
     pub fn extensions(mut self, extensions: &[Gext]) -> GetAssertionArgsBuilder<'a> {
         self.extensions = Some(extensions.to_vec());
         self
     }
 
     pub fn credential_id(mut self, credential_id: &[u8]) -> GetAssertionArgsBuilder<'a> {
-        self.credential_id = Some(credential_id.to_vec());
+        self.allow_list = allow_list_of(credential_id);
+        self
+    }
+
+    /// up=false : silent assertion without user interaction (the UP flag of the result is not set)
+    pub fn up(mut self, up: bool) -> GetAssertionArgsBuilder<'a> {
+        self.up = Some(up);
+        self
+    }
+
+    /// allowList - a list of more than one credential is probed in batches of
+    /// maxCredentialCountInList and narrowed down to the credential found by silent probing.
+    /// Assertion::credential_id is the credential that matched.
+    /// Fails with get_assertion_params::NoCredentials if none of them exists.
+    pub fn allow_list(
+        mut self,
+        allow_list: &[PublicKeyCredentialDescriptor],
+    ) -> GetAssertionArgsBuilder<'a> {
+        self.allow_list = allow_list.to_vec();
+        self
+    }
+
+    /// WebAuthn prf extension (eval / evalByCredential)
+    pub fn prf(mut self, prf: &PrfInputs) -> GetAssertionArgsBuilder<'a> {
+        self.prf = Some(prf.clone());
         self
     }
 
@@ -610,40 +874,161 @@ impl<'a> GetAssertionArgsBuilder<'a> {
             rpid: self.rpid,
             challenge: self.challenge,
             pin: self.pin,
-            credential_id: self.credential_id,
+            pin_provider: self.pin_provider,
+            allow_list: self.allow_list,
+            up: self.up,
             uv: self.uv,
+            user_verification: self.user_verification,
             extensions: self.extensions,
+            prf: self.prf,
         }
     }
 }
 pub fn get_assertion_with_args(cfg: &LibCfg, args: &GetAssertionArgs) -> Result<Vec<Assertion>> {
     let device = get_device(cfg)?;
+    let (allow_list, extensions) = create_get_assertion_params(&device, args)?;
+    let (provided_pin, user_verification) =
+        provide_pin(&device, args.pin_provider, args.user_verification, None)?;
 
-    let credential_id = if args.credential_id.is_some() {
-        args.credential_id.as_ref().unwrap().to_vec()
-    } else {
-        let dmy: [u8; 0] = [];
-        dmy.to_vec()
-    };
+    let mut asss = get_assertion::get_assertion(
+        &device,
+        &get_assertion::Params {
+            rpid: &args.rpid,
+            challenge: &args.challenge,
+            allow_list: &allow_list,
+            pin: provided_pin.as_ref().map(|pin| pin.as_str()).or(args.pin),
+            up: args.up.unwrap_or(true),
+            uv: args.uv,
+            user_verification,
+            extensions: extensions.as_ref(),
+        },
+    )?;
+
+    if args.prf.is_some() {
+        for ass in asss.iter_mut() {
+            ass.prf = Some(PrfOutputs::from_get_assertion_extensions(&ass.extensions));
+        }
+    }
+
+    Ok(asss)
+}
+
+/// Same as get_assertion_with_args, but the assertions of authenticatorGetNextAssertion
+/// are fetched one by one while iterating.
+/// e.g. for an account chooser - collect the users of the discoverable credentials, then pick one.
+pub fn get_assertion_iter_with_args(
+    cfg: &LibCfg,
+    args: &GetAssertionArgs,
+) -> Result<AssertionIter> {
+    let device = get_device(cfg)?;
+    let (allow_list, extensions) = create_get_assertion_params(&device, args)?;
+    let (provided_pin, user_verification) =
+        provide_pin(&device, args.pin_provider, args.user_verification, None)?;
+
+    let mut iter = get_assertion::get_assertion_iter(
+        device,
+        &get_assertion::Params {
+            rpid: &args.rpid,
+            challenge: &args.challenge,
+            allow_list: &allow_list,
+            pin: provided_pin.as_ref().map(|pin| pin.as_str()).or(args.pin),
+            up: args.up.unwrap_or(true),
+            uv: args.uv,
+            user_verification,
+            extensions: extensions.as_ref(),
+        },
+    )?;
+    iter.prf = args.prf.is_some();
+
+    Ok(iter)
+}
+
+// allowList and extensions (prf → hmac-secret)
+fn create_get_assertion_params(
+    device: &FidoKeyHid,
+    args: &GetAssertionArgs,
+) -> Result<(Vec<PublicKeyCredentialDescriptor>, Option<Vec<Gext>>)> {
+    let mut allow_list = args.allow_list.to_vec();
+
+    // prf → hmac-secret (evalByCredential of the allowList credential or eval)
+    let mut prf_extensions = args.extensions.clone().unwrap_or_default();
+    if let Some(prf) = &args.prf {
+        let credential_ids: Vec<Vec<u8>> = allow_list.iter().map(|x| x.id.to_vec()).collect();
+        prf.check_allow_list(&credential_ids).map_err(Error::msg)?;
+
+        // the salts depend on the credential, so find it first
+        if !prf.eval_by_credential.is_empty() && allow_list.len() > 1 {
+            let found = get_assertion::find_allowed_credential(device, &args.rpid, &allow_list)?
+                .ok_or_else(|| Error::new(NoCredentials))?;
+            allow_list = vec![found];
+        }
+
+        prf_extensions.retain(|ext| !matches!(ext, Gext::HmacSecret(_) | Gext::HmacSecret2(_)));
+        let credential_id = match allow_list.as_slice() {
+            [x] => x.id.to_vec(),
+            _ => vec![],
+        };
+        if let Some(ext) = prf.to_get_assertion_extension(&credential_id) {
+            prf_extensions.push(ext);
+        }
+    }
 
-    let extensions = if args.extensions.is_some() {
-        Some(args.extensions.as_ref().unwrap())
+    let extensions = if args.extensions.is_some() || args.prf.is_some() {
+        Some(prf_extensions)
     } else {
         None
     };
 
-    let asss = get_assertion::get_assertion(
-        &device,
-        &args.rpid,
-        &args.challenge,
-        &credential_id,
-        args.pin,
-        true,
-        args.uv,
-        extensions,
+    Ok((allow_list, extensions))
+}
+
+// PIN of the PinProvider (if any) and the user verification policy to use with it
+fn provide_pin(
+    device: &FidoKeyHid,
+    pin_provider: Option<PinProviderRef>,
+    user_verification: Option<UserVerification>,
+    rk: Option<bool>,
+) -> Result<(Option<Zeroizing<String>>, Option<UserVerification>)> {
+    match pin_provider {
+        Some(PinProviderRef(pin_provider)) => {
+            let policy = user_verification.unwrap_or(UserVerification::Preferred);
+            let pin =
+                client_pin::provide_pin_for_user_verification(device, pin_provider, policy, rk)?;
+            Ok((pin, Some(policy)))
+        }
+        None => Ok((None, user_verification)),
+    }
+}
+
+#[derive(Clone, Copy)]
+struct PinProviderRef<'a>(&'a dyn PinProvider);
+impl fmt::Debug for PinProviderRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "PinProvider")
+    }
+}
+
+/// Check whether credential_id belongs to the authenticator with a silent (up=false) assertion.
+/// No user interaction is required.
+/// Credentials that require user verification (credProtect level 3) are not found.
+pub fn credential_exists(cfg: &LibCfg, rpid: &str, credential_id: &[u8]) -> Result<bool> {
+    let found = find_credential(
+        cfg,
+        rpid,
+        &[PublicKeyCredentialDescriptor::new(credential_id)],
     )?;
+    Ok(found.is_some())
+}
 
-    Ok(asss)
+/// Find the first credential of the list that belongs to the authenticator
+/// with silent (up=false) assertions in batches of maxCredentialCountInList.
+pub fn find_credential(
+    cfg: &LibCfg,
+    rpid: &str,
+    list: &[PublicKeyCredentialDescriptor],
+) -> Result<Option<PublicKeyCredentialDescriptor>> {
+    let device = get_device(cfg)?;
+    get_assertion::find_allowed_credential(&device, rpid, list)
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -669,7 +1054,7 @@ pub fn enable_info_param(cfg: &LibCfg, info_param: &InfoParam) -> Result<bool> {
         InfoParam::VersionsFido21 => "FIDO_2_1",
         InfoParam::ExtensionsCredProtect => Mext::CredProtect(None).as_ref(),
         InfoParam::ExtensionsCredBlob => "credBlob",
-        InfoParam::ExtensionsLargeBlobKey => "credBlobKey",
+        InfoParam::ExtensionsLargeBlobKey => Mext::LargeBlobKey(None).as_ref(),
         InfoParam::ExtensionsMinPinLength => "minPinLength",
         InfoParam::ExtensionsHmacSecret => Mext::HmacSecret(None).as_ref(),
     };
@@ -710,21 +1095,16 @@ pub fn enable_info_option(cfg: &LibCfg, info_option: &InfoOption) -> Result<Opti
         InfoOption::UserVerificationMgmtPreview => "userVerificationMgmtPreview",
         InfoOption::BioEnroll => "bioEnroll",
     };
-    let ret = info.options.iter().find(|v| (*v).0 == find);
-    if let Some(v) = ret {
-        // v.1 == true or false
-        // - present and set to true.
-        // - present and set to false.
-        return Ok(Some(v.1));
-    }
-    // absent.
-    Ok(None)
+    // - present and set to true.
+    // - present and set to false.
+    // - absent.
+    Ok(info.option(find))
 }
 
 /// BioEnrollment - getFingerprintSensorInfo (CTAP 2.1-PRE)
 pub fn bio_enrollment_get_fingerprint_sensor_info(cfg: &LibCfg) -> Result<BioSensorInfo> {
-    let device = get_device(cfg)?;
-    let init = bio_enrollment::bio_enrollment_init(&device, None).map_err(Error::msg)?;
+    let mut device = get_device(cfg)?;
+    let init = bio_enrollment::bio_enrollment_init(&mut device, None).map_err(Error::msg)?;
 
     // 6.7.2. Get bio modality
     let data1 = bio_enrollment::bio_enrollment(&device, &init.0, None, None, None, None)
@@ -762,8 +1142,8 @@ pub fn bio_enrollment_begin(
     pin: &str,
     timeout_milliseconds: Option<u16>,
 ) -> Result<(EnrollStatus1, EnrollStatus2)> {
-    let device = get_device(cfg)?;
-    let init = bio_enrollment::bio_enrollment_init(&device, Some(pin))?;
+    let mut device = get_device(cfg)?;
+    let init = bio_enrollment::bio_enrollment_init(&mut device, Some(pin))?;
 
     let data = bio_enrollment::bio_enrollment(
         &device,
@@ -843,8 +1223,8 @@ pub fn bio_enrollment_cancel(cfg: &LibCfg, enroll_status: &EnrollStatus1) -> Res
 /// BioEnrollment - enumerateEnrollments (CTAP 2.1-PRE)
 /// 6.7.6. Enumerate enrollments
 pub fn bio_enrollment_enumerate_enrollments(cfg: &LibCfg, pin: &str) -> Result<Vec<TemplateInfo>> {
-    let device = get_device(cfg)?;
-    let init = bio_enrollment::bio_enrollment_init(&device, Some(pin))?;
+    let mut device = get_device(cfg)?;
+    let init = bio_enrollment::bio_enrollment_init(&mut device, Some(pin))?;
     let pin_token = init.1.unwrap();
 
     let data = bio_enrollment::bio_enrollment(
@@ -872,8 +1252,8 @@ pub fn bio_enrollment_set_friendly_name(
 ) -> Result<()> {
     let template_info = TemplateInfo::new(template_id, Some(template_name));
 
-    let device = get_device(cfg)?;
-    let init = bio_enrollment::bio_enrollment_init(&device, Some(pin))?;
+    let mut device = get_device(cfg)?;
+    let init = bio_enrollment::bio_enrollment_init(&mut device, Some(pin))?;
     let pin_token = init.1.unwrap();
 
     let data = bio_enrollment::bio_enrollment(
@@ -892,8 +1272,8 @@ pub fn bio_enrollment_set_friendly_name(
 
 /// 6.7.8. Remove enrollment
 pub fn bio_enrollment_remove(cfg: &LibCfg, pin: &str, template_id: &[u8]) -> Result<()> {
-    let device = get_device(cfg)?;
-    let init = bio_enrollment::bio_enrollment_init(&device, Some(pin))?;
+    let mut device = get_device(cfg)?;
+    let init = bio_enrollment::bio_enrollment_init(&mut device, Some(pin))?;
     let pin_token = init.1.unwrap();
 
     let template_info = TemplateInfo::new(template_id, None);
@@ -916,9 +1296,9 @@ pub fn credential_management_get_creds_metadata(
     cfg: &LibCfg,
     pin: Option<&str>,
 ) -> Result<credential_management_params::CredentialsCount> {
-    let device = get_device(cfg)?;
+    let mut device = get_device(cfg)?;
     let meta = credential_management::credential_management(
-        &device,
+        &mut device,
         pin,
         credential_management_command::SubCommand::GetCredsMetadata,
         None,
@@ -933,32 +1313,18 @@ pub fn credential_management_enumerate_rps(
     cfg: &LibCfg,
     pin: Option<&str>,
 ) -> Result<Vec<credential_management_params::Rp>> {
-    let device = get_device(cfg)?;
-    let mut datas: Vec<credential_management_params::Rp> = Vec::new();
-    let data = credential_management::credential_management(
-        &device,
-        pin,
-        credential_management_command::SubCommand::EnumerateRPsBegin,
-        None,
-        None,
-        None,
-    )?;
-    if data.total_rps > 0 {
-        datas.push(credential_management_params::Rp::new(&data));
-        let roop_n = data.total_rps - 1;
-        for _ in 0..roop_n {
-            let data = credential_management::credential_management(
-                &device,
-                pin,
-                credential_management_command::SubCommand::EnumerateRPsGetNextRp,
-                None,
-                None,
-                None,
-            )?;
-            datas.push(credential_management_params::Rp::new(&data));
-        }
-    }
-    Ok(datas)
+    credential_management_enumerate_rps_iter(cfg, pin)?.collect()
+}
+
+/// CredentialManagement - enumerateRPsBegin & enumerateRPsNext one by one (CTAP 2.1-PRE)
+/// - all RPs are enumerated with one session and pinUvAuthToken
+pub fn credential_management_enumerate_rps_iter(
+    cfg: &LibCfg,
+    pin: Option<&str>,
+) -> Result<credential_management_params::RpIter> {
+    let mut device = get_device(cfg)?;
+    let session = credential_management::Session::open(&mut device, pin)?;
+    session.enumerate_rps(device)
 }
 
 /// CredentialManagement - enumerateCredentialsBegin & enumerateCredentialsNext (CTAP 2.1-PRE)
@@ -967,33 +1333,95 @@ pub fn credential_management_enumerate_credentials(
     pin: Option<&str>,
     rpid_hash: &[u8],
 ) -> Result<Vec<credential_management_params::Credential>> {
+    credential_management_enumerate_credentials_iter(cfg, pin, rpid_hash)?.collect()
+}
+
+/// CredentialManagement - enumerateCredentialsBegin & enumerateCredentialsNext one by one (CTAP 2.1-PRE)
+/// - all credentials of the RP are enumerated with one session and pinUvAuthToken
+pub fn credential_management_enumerate_credentials_iter(
+    cfg: &LibCfg,
+    pin: Option<&str>,
+    rpid_hash: &[u8],
+) -> Result<credential_management_params::CredentialIter> {
+    let mut device = get_device(cfg)?;
+    let session = credential_management::Session::open(&mut device, pin)?;
+    let rp = credential_management_params::Rp {
+        rpid_hash: rpid_hash.to_vec(),
+        ..Default::default()
+    };
+    session.enumerate_credentials(device, &rp)
+}
+
+/// CredentialManagement - enumerate all RPs and their credentials (CTAP 2.1-PRE)
+/// - one session and pinUvAuthToken are used for the whole tree
+pub fn credential_management_enumerate_all(
+    cfg: &LibCfg,
+    pin: Option<&str>,
+) -> Result<Vec<credential_management_params::RpCredentials>> {
+    credential_management_enumerate_rps_iter(cfg, pin)?.into_tree()
+}
+
+/// CredentialManagement - get a persistentPinUvAuthToken with the pcmr permission (CTAP 2.2)
+/// - requires the perCredMgmtRO option
+/// - the token can be stored and used by the *_with_token functions without the PIN
+pub fn credential_management_get_persistent_token(
+    cfg: &LibCfg,
+    pin: &str,
+) -> Result<credential_management_params::PersistentPinUvAuthToken> {
     let device = get_device(cfg)?;
-    let mut datas: Vec<credential_management_params::Credential> = Vec::new();
+    credential_management::get_persistent_token(&device, pin)
+}
 
-    let data = credential_management::credential_management(
-        &device,
-        pin,
-        credential_management_command::SubCommand::EnumerateCredentialsBegin,
-        Some(rpid_hash.to_vec()),
-        None,
-        None,
-    )?;
-    datas.push(credential_management_params::Credential::new(&data));
-    if data.total_credentials > 0 {
-        let roop_n = data.total_credentials - 1;
-        for _ in 0..roop_n {
-            let data = credential_management::credential_management(
-                &device,
-                pin,
-                credential_management_command::SubCommand::EnumerateCredentialsGetNextCredential,
-                Some(rpid_hash.to_vec()),
-                None,
-                None,
-            )?;
-            datas.push(credential_management_params::Credential::new(&data));
-        }
-    }
-    Ok(datas)
+/// CredentialManagement - getCredsMetadata with a persistentPinUvAuthToken (CTAP 2.2)
+pub fn credential_management_get_creds_metadata_with_token(
+    cfg: &LibCfg,
+    token: &credential_management_params::PersistentPinUvAuthToken,
+) -> Result<credential_management_params::CredentialsCount> {
+    let mut device = get_device(cfg)?;
+    let session = credential_management::Session::open_with_token(&mut device, token)?;
+    let meta = session
+        .send(
+            &device,
+            credential_management_command::SubCommand::GetCredsMetadata,
+            None,
+            None,
+            None,
+        )
+        .map_err(Error::msg)?;
+    Ok(credential_management_params::CredentialsCount::new(&meta))
+}
+
+/// CredentialManagement - enumerate RPs one by one with a persistentPinUvAuthToken (CTAP 2.2)
+pub fn credential_management_enumerate_rps_iter_with_token(
+    cfg: &LibCfg,
+    token: &credential_management_params::PersistentPinUvAuthToken,
+) -> Result<credential_management_params::RpIter> {
+    let mut device = get_device(cfg)?;
+    let session = credential_management::Session::open_with_token(&mut device, token)?;
+    session.enumerate_rps(device)
+}
+
+/// CredentialManagement - enumerate the credentials of an RP one by one with a persistentPinUvAuthToken (CTAP 2.2)
+pub fn credential_management_enumerate_credentials_iter_with_token(
+    cfg: &LibCfg,
+    token: &credential_management_params::PersistentPinUvAuthToken,
+    rpid_hash: &[u8],
+) -> Result<credential_management_params::CredentialIter> {
+    let mut device = get_device(cfg)?;
+    let session = credential_management::Session::open_with_token(&mut device, token)?;
+    let rp = credential_management_params::Rp {
+        rpid_hash: rpid_hash.to_vec(),
+        ..Default::default()
+    };
+    session.enumerate_credentials(device, &rp)
+}
+
+/// CredentialManagement - enumerate all RPs and their credentials with a persistentPinUvAuthToken (CTAP 2.2)
+pub fn credential_management_enumerate_all_with_token(
+    cfg: &LibCfg,
+    token: &credential_management_params::PersistentPinUvAuthToken,
+) -> Result<Vec<credential_management_params::RpCredentials>> {
+    credential_management_enumerate_rps_iter_with_token(cfg, token)?.into_tree()
 }
 
 /// CredentialManagement - deleteCredential (CTAP 2.1-PRE)
@@ -1002,9 +1430,9 @@ pub fn credential_management_delete_credential(
     pin: Option<&str>,
     pkcd: Option<PublicKeyCredentialDescriptor>,
 ) -> Result<()> {
-    let device = get_device(cfg)?;
+    let mut device = get_device(cfg)?;
     credential_management::credential_management(
-        &device,
+        &mut device,
         pin,
         credential_management_command::SubCommand::DeleteCredential,
         None,
@@ -1021,9 +1449,9 @@ pub fn credential_management_update_user_information(
     pkcd: Option<PublicKeyCredentialDescriptor>,
     pkcue: Option<public_key_credential_user_entity::PublicKeyCredentialUserEntity>,
 ) -> Result<()> {
-    let device = get_device(cfg)?;
+    let mut device = get_device(cfg)?;
     credential_management::credential_management(
-        &device,
+        &mut device,
         pin,
         credential_management_command::SubCommand::UpdateUserInformation,
         None,
@@ -1033,6 +1461,52 @@ pub fn credential_management_update_user_information(
     Ok(())
 }
 
+/// LargeBlobs - get the large-blob array (CTAP 2.1)
+pub fn large_blobs_get_array(cfg: &LibCfg) -> Result<LargeBlobArray> {
+    let device = get_device(cfg)?;
+    large_blobs::get_large_blob_array(&device)
+}
+
+/// LargeBlobs - set the large-blob array (CTAP 2.1)
+pub fn large_blobs_set_array(
+    cfg: &LibCfg,
+    pin: Option<&str>,
+    array: &LargeBlobArray,
+) -> Result<()> {
+    let device = get_device(cfg)?;
+    large_blobs::set_large_blob_array(&device, pin, array)
+}
+
+/// LargeBlobs - read the data of the credential specified by largeBlobKey (CTAP 2.1)
+pub fn large_blobs_read(cfg: &LibCfg, large_blob_key: &[u8]) -> Result<Option<Vec<u8>>> {
+    let device = get_device(cfg)?;
+    let array = large_blobs::get_large_blob_array(&device)?;
+    Ok(array.find(large_blob_key))
+}
+
+/// LargeBlobs - write the data of the credential specified by largeBlobKey (CTAP 2.1)
+pub fn large_blobs_write(
+    cfg: &LibCfg,
+    pin: Option<&str>,
+    large_blob_key: &[u8],
+    data: &[u8],
+) -> Result<()> {
+    let device = get_device(cfg)?;
+    let mut array = large_blobs::get_large_blob_array(&device)?;
+    array.put(large_blob_key, data).map_err(Error::msg)?;
+    large_blobs::set_large_blob_array(&device, pin, &array)
+}
+
+/// LargeBlobs - delete the data of the credential specified by largeBlobKey (CTAP 2.1)
+pub fn large_blobs_delete(cfg: &LibCfg, pin: Option<&str>, large_blob_key: &[u8]) -> Result<()> {
+    let device = get_device(cfg)?;
+    let mut array = large_blobs::get_large_blob_array(&device)?;
+    if array.remove(large_blob_key) {
+        large_blobs::set_large_blob_array(&device, pin, &array)?;
+    }
+    Ok(())
+}
+
 /// Selection (CTAP 2.1)
 pub fn selection(cfg: &LibCfg) -> Result<String> {
     let device = get_device(cfg)?;
@@ -1058,6 +1532,52 @@ pub fn config(cfg: &LibCfg) -> Result<String> {
 mod tests {
     use super::*;
 
+    // COSE_Key of the authenticator for the ECDH key agreement (a fresh P-256 key pair)
+    fn peer_key_fixture() -> cose::CoseKey {
+        let rng = ring::rand::SystemRandom::new();
+        let peer_private_key =
+            ring::agreement::EphemeralPrivateKey::generate(&ring::agreement::ECDH_P256, &rng)
+                .unwrap();
+        let peer_public_key = peer_private_key.compute_public_key().unwrap();
+        p256::P256Key::from_bytes(peer_public_key.as_ref())
+            .unwrap()
+            .to_cose()
+    }
+
+    // COSE_Key map - kty(1), alg(3) and the key type parameters
+    fn cose_key_fixture(
+        kty: i128,
+        alg: i128,
+        params: Vec<(i128, serde_cbor::Value)>,
+    ) -> serde_cbor::Value {
+        let mut map = std::collections::BTreeMap::new();
+        map.insert(
+            serde_cbor::Value::Integer(1),
+            serde_cbor::Value::Integer(kty),
+        );
+        map.insert(
+            serde_cbor::Value::Integer(3),
+            serde_cbor::Value::Integer(alg),
+        );
+        for (member, val) in params {
+            map.insert(serde_cbor::Value::Integer(member), val);
+        }
+        serde_cbor::Value::Map(map)
+    }
+
+    // COSE_Key map of an ES256 public key (x, y)
+    fn es256_cose_key_fixture(x: &[u8], y: &[u8]) -> serde_cbor::Value {
+        cose_key_fixture(
+            2,
+            -7,
+            vec![
+                (-1, serde_cbor::Value::Integer(1)),
+                (-2, serde_cbor::Value::Bytes(x.to_vec())),
+                (-3, serde_cbor::Value::Bytes(y.to_vec())),
+            ],
+        )
+    }
+
     #[test]
     fn test_client_pin_get_keyagreement() {
         let hid_params = HidParam::get();
@@ -1098,7 +1618,7 @@ mod tests {
 
             params.pin_auth = pin_auth.to_vec();
 
-            make_credential_command::create_payload(params, None)
+            make_credential_command::create_payload(params, None, None)
         };
 
         //println!(
@@ -1111,6 +1631,169 @@ mod tests {
         assert_eq!(command, check);
     }
 
+    #[test]
+    fn test_make_credential_cred_blob_command() {
+        let params =
+            make_credential_command::Params::new("test.com", b"this is challenge".to_vec(), vec![]);
+        let extensions = vec![Mext::CredBlob((Some(b"this is cred blob".to_vec()), None))];
+        let send_payload = make_credential_command::create_payload(params, Some(&extensions), None);
+
+        let maps = util::cbor_bytes_to_map(&send_payload[1..]).unwrap();
+        let ext = maps.get(&serde_cbor::Value::Integer(0x06)).unwrap();
+        let cred_blob = util::cbor_get_bytes_from_map(ext, "credBlob").unwrap();
+        assert_eq!(cred_blob, b"this is cred blob");
+    }
+
+    #[test]
+    fn test_make_credential_key_types_command() {
+        let mut params = make_credential_command::Params::new(
+            "test.com",
+            b"this is challenge".to_vec(),
+            b"12345678".to_vec(),
+        );
+        params.key_types = vec![
+            CredentialSupportedKeyType::Ecdsa384,
+            CredentialSupportedKeyType::Ecdsa256,
+            CredentialSupportedKeyType::Rs256,
+        ];
+        let send_payload = make_credential_command::create_payload(params, None, None);
+
+        let maps = util::cbor_bytes_to_map(&send_payload[1..]).unwrap();
+        let algs: Vec<i128> = match maps.get(&serde_cbor::Value::Integer(0x04)).unwrap() {
+            serde_cbor::Value::Array(xs) => xs
+                .iter()
+                .map(|x| match x {
+                    serde_cbor::Value::Map(m) => {
+                        match m.get(&serde_cbor::Value::Text("alg".to_string())) {
+                            Some(serde_cbor::Value::Integer(alg)) => *alg,
+                            _ => 0,
+                        }
+                    }
+                    _ => 0,
+                })
+                .collect(),
+            _ => vec![],
+        };
+        assert_eq!(algs, vec![-35, -7, -257]);
+    }
+
+    #[test]
+    fn test_make_credential_exclude_list_command() {
+        let mut params = make_credential_command::Params::new(
+            "test.com",
+            b"this is challenge".to_vec(),
+            b"12345678".to_vec(),
+        );
+        params.exclude_list = vec![
+            PublicKeyCredentialDescriptor::new(b"credential-1"),
+            PublicKeyCredentialDescriptor::new(b"credential-2"),
+        ];
+        let send_payload = make_credential_command::create_payload(params, None, None);
+
+        let maps = util::cbor_bytes_to_map(&send_payload[1..]).unwrap();
+        match maps.get(&serde_cbor::Value::Integer(0x05)).unwrap() {
+            serde_cbor::Value::Array(xs) => {
+                assert_eq!(xs.len(), 2);
+                assert_eq!(
+                    util::cbor_get_bytes_from_map(&xs[1], "id").unwrap(),
+                    b"credential-2".to_vec()
+                );
+            }
+            _ => panic!("excludeList is not an array"),
+        }
+
+        // a list larger than maxCredentialCountInList is never sent empty
+        let info = get_info_params::Info {
+            max_credential_count_in_list: 1,
+            ..Default::default()
+        };
+        let exclude_list = vec![
+            PublicKeyCredentialDescriptor::new(b"credential-1"),
+            PublicKeyCredentialDescriptor::new(b"credential-2"),
+        ];
+        let batches = get_assertion::probe_batches(&info, &exclude_list);
+        assert!(!get_assertion::fits_in_one_request(&info, &batches));
+
+        let mut params = make_credential_command::Params::new(
+            "test.com",
+            b"this is challenge".to_vec(),
+            b"12345678".to_vec(),
+        );
+        params.exclude_list = get_assertion::credential_list_to_send(batches, None);
+        let send_payload = make_credential_command::create_payload(params, None, None);
+
+        let maps = util::cbor_bytes_to_map(&send_payload[1..]).unwrap();
+        match maps.get(&serde_cbor::Value::Integer(0x05)) {
+            Some(serde_cbor::Value::Array(xs)) => assert_eq!(xs.len(), 1),
+            _ => panic!("excludeList is not sent"),
+        }
+    }
+
+    #[test]
+    fn test_ctaphid_error_status() {
+        let err = ctaphid::CtapHidError {
+            status: Some(0x19),
+            message: ctapdef::get_ctap_status_message(0x19),
+        };
+        assert!(err.is_status(0x19));
+        assert!(!err.is_status(0x2E));
+        assert!(!ctaphid::CtapHidError::from("read err".to_string()).is_status(0x19));
+    }
+
+    #[test]
+    fn test_make_credential_enterprise_attestation_command() {
+        let mut params = make_credential_command::Params::new(
+            "test.com",
+            b"this is challenge".to_vec(),
+            b"12345678".to_vec(),
+        );
+        params.enterprise_attestation = Some(EnterpriseAttestation::PlatformManaged);
+        let send_payload = make_credential_command::create_payload(params, None, None);
+
+        let maps = util::cbor_bytes_to_map(&send_payload[1..]).unwrap();
+        assert_eq!(
+            maps.get(&serde_cbor::Value::Integer(0x0A)),
+            Some(&serde_cbor::Value::Integer(2))
+        );
+    }
+
+    #[test]
+    fn test_check_pin_policy() {
+        use crate::client_pin_params::PinPolicyViolation;
+
+        let mut info = get_info_params::Info::default();
+        assert!(client_pin::check_pin_policy(&info, "1234", None).is_ok());
+        assert_eq!(
+            client_pin::check_pin_policy(&info, "123", None),
+            Err(PinPolicyViolation::TooShort {
+                min_pin_length: 4,
+                length: 3
+            })
+        );
+
+        // 4 code points (12 bytes)
+        info.min_pin_length = 4;
+        assert!(client_pin::check_pin_policy(&info, "暗証番号", None).is_ok());
+        info.min_pin_length = 6;
+        assert!(client_pin::check_pin_policy(&info, "暗証番号", None).is_err());
+
+        let pin = "1".repeat(64);
+        assert_eq!(
+            client_pin::check_pin_policy(&info, &pin, None),
+            Err(PinPolicyViolation::TooLong {
+                max_pin_bytes: 63,
+                length: 64
+            })
+        );
+
+        info.force_pin_change = true;
+        assert_eq!(
+            client_pin::check_pin_policy(&info, "123456", Some("123456")),
+            Err(PinPolicyViolation::SameAsCurrentPin)
+        );
+        assert!(client_pin::check_pin_policy(&info, "654321", Some("123456")).is_ok());
+    }
+
     #[test]
     fn test_create_pin_auth() {
         let out_bytes = hex::decode("1A81CD600A1F6CF4BE5260FE3257B241").unwrap();
@@ -1133,4 +1816,1032 @@ mod tests {
         let check = "1BCF27BDA4891AFA5F53CC027B8835564E35A8E3B631AA0F0563299296AD5909".to_string();
         assert_eq!(check, hex::encode(sig).to_uppercase());
     }
+
+    #[test]
+    fn test_get_assertion_large_blob_key_command() {
+        let params =
+            get_assertion_command::Params::new("test.com", b"this is challenge".to_vec(), vec![]);
+        let extensions = vec![Gext::LargeBlobKey(Some(true))];
+        let send_payload = get_assertion_command::create_payload(params, Some(&extensions), None);
+
+        let maps = util::cbor_bytes_to_map(&send_payload[1..]).unwrap();
+        let ext = maps.get(&serde_cbor::Value::Integer(0x04)).unwrap();
+        let mut ext_val = std::collections::BTreeMap::new();
+        ext_val.insert(
+            serde_cbor::Value::Text("largeBlobKey".to_string()),
+            serde_cbor::Value::Bool(true),
+        );
+        assert_eq!(*ext, serde_cbor::Value::Map(ext_val));
+
+        // LargeBlobKey(None) / CredBlob((None, _)) / MinPinLength((None, _)) are not sent
+        let params =
+            get_assertion_command::Params::new("test.com", b"this is challenge".to_vec(), vec![]);
+        let extensions = vec![Gext::LargeBlobKey(None), Gext::CredBlob((None, None))];
+        let send_payload = get_assertion_command::create_payload(params, Some(&extensions), None);
+        let maps = util::cbor_bytes_to_map(&send_payload[1..]).unwrap();
+        assert!(!maps.contains_key(&serde_cbor::Value::Integer(0x04)));
+
+        let params =
+            make_credential_command::Params::new("test.com", b"this is challenge".to_vec(), vec![]);
+        let extensions = vec![Mext::LargeBlobKey(None), Mext::MinPinLength((None, None))];
+        let send_payload = make_credential_command::create_payload(params, Some(&extensions), None);
+        let maps = util::cbor_bytes_to_map(&send_payload[1..]).unwrap();
+        assert_eq!(
+            maps.get(&serde_cbor::Value::Integer(0x06)),
+            Some(&serde_cbor::Value::Map(Default::default()))
+        );
+    }
+
+    #[test]
+    fn test_hmac_secret_pin_uv_auth_protocol_two() {
+        let peer_key = peer_key_fixture();
+
+        let hmac_ext = hmac::HmacExt {
+            shared_secret: ss::SharedSecret::new_v2(&peer_key).unwrap(),
+            ..Default::default()
+        };
+
+        // IV(16) || output1 || output2
+        let outputs = [[0x01u8; 32], [0x02u8; 32]].concat();
+        let output_enc = hmac_ext.shared_secret.encrypt_message(&outputs).unwrap();
+        assert_eq!(output_enc.len(), 16 + 64);
+        assert_eq!(
+            hmac_ext.decrypt_output(&output_enc).unwrap(),
+            ([0x01u8; 32], Some([0x02u8; 32]))
+        );
+        assert_eq!(hmac_ext.shared_secret.authenticate(&output_enc).len(), 32);
+
+        // pinUvAuthProtocol(0x04) is sent in the hmac-secret input
+        let params =
+            get_assertion_command::Params::new("test.com", b"this is challenge".to_vec(), vec![]);
+        let send_payload = get_assertion_command::create_payload(params, None, Some(&hmac_ext));
+        let maps = util::cbor_bytes_to_map(&send_payload[1..]).unwrap();
+        let ext = maps.get(&serde_cbor::Value::Integer(0x04)).unwrap();
+        if let serde_cbor::Value::Map(ext) = ext {
+            let hmac_secret = ext
+                .get(&serde_cbor::Value::Text("hmac-secret".to_string()))
+                .unwrap();
+            if let serde_cbor::Value::Map(hmac_secret) = hmac_secret {
+                assert_eq!(
+                    hmac_secret.get(&serde_cbor::Value::Integer(0x04)),
+                    Some(&serde_cbor::Value::Integer(2))
+                );
+            } else {
+                panic!("hmac-secret is not a map");
+            }
+        } else {
+            panic!("extensions is not a map");
+        }
+    }
+
+    #[test]
+    fn test_hmac_secret_mc_payload() {
+        let peer_key = peer_key_fixture();
+
+        let hmac_ext = hmac::HmacExt {
+            shared_secret: ss::SharedSecret::new(&peer_key).unwrap(),
+            ..Default::default()
+        };
+
+        // hmac-secret-mc is sent together with "hmac-secret": true
+        let params = make_credential_command::Params::new(
+            "test.com",
+            b"this is challenge".to_vec(),
+            b"12345678".to_vec(),
+        );
+        let extensions = vec![Mext::HmacSecretMc((Some([0x01u8; 32]), None))];
+        let send_payload =
+            make_credential_command::create_payload(params, Some(&extensions), Some(&hmac_ext));
+        let maps = util::cbor_bytes_to_map(&send_payload[1..]).unwrap();
+        let ext = maps.get(&serde_cbor::Value::Integer(0x06)).unwrap();
+        if let serde_cbor::Value::Map(ext) = ext {
+            assert_eq!(
+                ext.get(&serde_cbor::Value::Text("hmac-secret".to_string())),
+                Some(&serde_cbor::Value::Bool(true))
+            );
+            assert_eq!(
+                ext.get(&serde_cbor::Value::Text("hmac-secret-mc".to_string())),
+                Some(&hmac_ext.to_value())
+            );
+        } else {
+            panic!("extensions is not a map");
+        }
+    }
+
+    #[test]
+    fn test_prf_inputs() {
+        let eval = prf_params::PrfValues::new(b"this is prf input", None);
+        let by_cred = prf_params::PrfValues::new(b"first", Some(b"second"));
+        let prf = PrfInputs::new()
+            .eval(eval.clone())
+            .eval_by_credential(b"credential-1", by_cred.clone());
+
+        // salt1 = SHA-256("WebAuthn PRF" || 0x00 || input)
+        let (salt1, salt2) = eval.to_salts();
+        assert_eq!(
+            hex::encode(salt1).to_uppercase(),
+            "EAA7FE3BFBFFB3FA3F902F3103B567A3B4203DE622578D4D8FBF559FD4FEEDB5"
+        );
+        assert!(salt2.is_none());
+
+        assert_eq!(prf.select(b"credential-1"), Some(&by_cred));
+        assert_eq!(prf.select(b"credential-2"), Some(&eval));
+        assert!(matches!(
+            prf.to_get_assertion_extension(b"credential-1"),
+            Some(Gext::HmacSecret2((Some(_), Some(_))))
+        ));
+
+        assert!(prf.check_allow_list(&[b"credential-1".to_vec()]).is_ok());
+        assert!(prf.check_allow_list(&[b"credential-2".to_vec()]).is_err());
+        assert!(prf.check_allow_list(&[]).is_err());
+        assert!(prf.to_make_credential_extensions(true).is_err());
+
+        // hmac-secret-mc is only sent when the authenticator supports it
+        let prf = PrfInputs::new().eval(eval.clone());
+        let extensions = prf.to_make_credential_extensions(true).unwrap();
+        assert!(matches!(
+            extensions[..],
+            [Mext::HmacSecret(Some(true)), Mext::HmacSecretMc(_)]
+        ));
+        let extensions = prf.to_make_credential_extensions(false).unwrap();
+        assert!(matches!(extensions[..], [Mext::HmacSecret(Some(true))]));
+        let outputs = PrfOutputs::from_make_credential_extensions(&[Mext::HmacSecret(Some(true))]);
+        assert_eq!(outputs.enabled, Some(true));
+        assert!(outputs.results.is_none());
+
+        let outputs = PrfOutputs::from_get_assertion_extensions(&[Gext::HmacSecret2((
+            Some([0x01u8; 32]),
+            Some([0x02u8; 32]),
+        ))]);
+        assert_eq!(
+            outputs.results,
+            Some(prf_params::PrfValues::new(
+                &[0x01u8; 32],
+                Some(&[0x02u8; 32])
+            ))
+        );
+    }
+
+    #[test]
+    fn test_get_assertion_allow_list_command() {
+        let allow_list = vec![
+            PublicKeyCredentialDescriptor::new(b"credential-1").transports(&["usb", "nfc"]),
+            PublicKeyCredentialDescriptor::new(b"credential-2"),
+        ];
+        let params = get_assertion_command::Params::new(
+            "test.com",
+            b"this is challenge".to_vec(),
+            allow_list,
+        );
+        let send_payload = get_assertion_command::create_payload(params, None, None);
+
+        let maps = util::cbor_bytes_to_map(&send_payload[1..]).unwrap();
+        match maps.get(&serde_cbor::Value::Integer(0x03)).unwrap() {
+            serde_cbor::Value::Array(xs) => {
+                assert_eq!(xs.len(), 2);
+                assert_eq!(
+                    util::cbor_get_bytes_from_map(&xs[0], "id").unwrap(),
+                    b"credential-1".to_vec()
+                );
+                if let serde_cbor::Value::Map(m) = &xs[0] {
+                    assert_eq!(
+                        m.get(&serde_cbor::Value::Text("transports".to_string())),
+                        Some(&serde_cbor::Value::Array(vec![
+                            serde_cbor::Value::Text("usb".to_string()),
+                            serde_cbor::Value::Text("nfc".to_string()),
+                        ]))
+                    );
+                }
+                if let serde_cbor::Value::Map(m) = &xs[1] {
+                    assert!(!m.contains_key(&serde_cbor::Value::Text("transports".to_string())));
+                }
+            }
+            _ => panic!("allowList is not an array"),
+        }
+    }
+
+    #[test]
+    fn test_large_blob_array() {
+        // initial serialized large-blob array
+        let empty = hex::decode("8076BE8B528D0075F7AAE98D6FA57A6D3C").unwrap();
+        let mut array = LargeBlobArray::parse(&empty).unwrap();
+        assert!(array.entries.is_empty());
+        assert_eq!(array.serialize(), empty);
+
+        let key1 = [0x01u8; 32];
+        let key2 = [0x02u8; 32];
+        array.put(&key1, b"this is large blob").unwrap();
+        array.put(&key2, b"this is another large blob").unwrap();
+        array.put(&key1, b"this is new large blob").unwrap();
+
+        let array = LargeBlobArray::parse(&array.serialize()).unwrap();
+        assert_eq!(array.entries.len(), 2);
+        assert_eq!(array.find(&key1).unwrap(), b"this is new large blob");
+        assert_eq!(array.find(&key2).unwrap(), b"this is another large blob");
+        assert!(array.find(&[0x03u8; 32]).is_none());
+
+        let mut broken = array.serialize();
+        broken[1] ^= 0xff;
+        assert!(LargeBlobArray::parse(&broken).is_err());
+    }
+
+    #[test]
+    fn test_authenticator_data() {
+        use auth_data::{AttestedCredentialData, AuthenticatorData};
+        use serde_cbor::Value;
+
+        let mut auth_data = AuthenticatorData {
+            rpid_hash: vec![0xaa; 32],
+            sign_count: 0x01020304,
+            attested_credential_data: Some(AttestedCredentialData {
+                aaguid: vec![0xbb; 16],
+                credential_id: vec![0xcc; 20],
+                credential_public_key: es256_cose_key_fixture(&[0x11; 32], &[0x22; 32]),
+            }),
+            ..Default::default()
+        };
+        auth_data.flags.user_present_result = true;
+        auth_data.flags.backup_eligibility = true;
+        auth_data
+            .extensions
+            .insert("credProtect".to_string(), Value::Integer(2));
+
+        let bytes = auth_data.serialize().unwrap();
+        assert_eq!(bytes[32], 0x01 | 0x08 | 0x40 | 0x80);
+        assert_eq!(&bytes[33..37], &[0x01, 0x02, 0x03, 0x04]);
+
+        let parsed = AuthenticatorData::parse(&bytes).unwrap();
+        assert!(parsed.flags.attested_credential_data_included);
+        assert!(parsed.flags.extension_data_included);
+        assert!(parsed.flags.backup_eligibility);
+        assert!(!parsed.flags.backup_state);
+        let data = parsed.attested_credential_data.as_ref().unwrap();
+        assert_eq!(data.credential_id, vec![0xcc; 20]);
+        assert_eq!(data.algorithm().unwrap(), -7);
+        assert_eq!(parsed.extensions["credProtect"], Value::Integer(2));
+        assert_eq!(parsed.serialize().unwrap(), bytes);
+
+        assert!(AuthenticatorData::parse(&bytes[..36]).is_err());
+        let mut trailing = bytes.to_vec();
+        trailing.push(0x00);
+        assert!(AuthenticatorData::parse(&trailing).is_err());
+    }
+
+    #[test]
+    fn test_backup_flags() {
+        let flags = auth_data::Flags::parse(0x1d).unwrap();
+        assert!(flags.user_present_result);
+        assert!(flags.user_verified_result);
+        assert!(flags.backup_eligibility);
+        assert!(flags.backup_state);
+        assert_eq!(flags.as_u8(), 0x1d);
+
+        // BS without BE is invalid
+        let rpid = "test.com";
+        let assertion = get_assertion_params::Assertion {
+            rpid_hash: ring::digest::digest(&ring::digest::SHA256, rpid.as_bytes())
+                .as_ref()
+                .to_vec(),
+            flags: auth_data::Flags::parse(0x11).unwrap(),
+            ..Default::default()
+        };
+        let result = verifier::verify_assertion_with_result(rpid, &[], &[], &assertion);
+        assert!(!result.is_success);
+        assert!(!result.backup_state);
+    }
+
+    #[test]
+    fn test_get_info_response() {
+        use serde_cbor::Value;
+        use std::collections::BTreeMap;
+
+        let text = |s: &str| Value::Text(s.to_string());
+        let mut options = BTreeMap::new();
+        options.insert(text("rk"), Value::Bool(true));
+        options.insert(text("clientPin"), Value::Bool(false));
+        let mut alg = BTreeMap::new();
+        alg.insert(text("alg"), Value::Integer(-7));
+        alg.insert(text("type"), text("public-key"));
+        let mut certifications = BTreeMap::new();
+        certifications.insert(text("FIDO"), Value::Integer(2));
+
+        let mut map = BTreeMap::new();
+        map.insert(Value::Integer(0x01), Value::Array(vec![text("FIDO_2_2")]));
+        map.insert(Value::Integer(0x04), Value::Map(options));
+        map.insert(Value::Integer(0x0A), Value::Array(vec![Value::Map(alg)]));
+        map.insert(Value::Integer(0x13), Value::Map(certifications));
+        map.insert(Value::Integer(0x16), Value::Array(vec![text("packed")]));
+        map.insert(Value::Integer(0x17), Value::Integer(0));
+        map.insert(Value::Integer(0x18), Value::Bool(true));
+        map.insert(Value::Integer(0x1D), Value::Integer(63));
+        map.insert(Value::Integer(0x40), Value::Bool(true));
+        let bytes = serde_cbor::to_vec(&Value::Map(map)).unwrap();
+
+        let info = get_info_response::parse_cbor(&bytes).unwrap();
+        assert_eq!(info.option("rk"), Some(true));
+        assert_eq!(info.option("clientPin"), Some(false));
+        assert_eq!(info.option("uv"), None);
+        assert_eq!(info.algorithms.len(), 1);
+        assert_eq!(info.algorithms[0].ctype, "public-key");
+        assert_eq!(info.algorithms[0].alg, -7);
+        assert_eq!(info.certifications["FIDO"], 2);
+        assert_eq!(info.attestation_formats, vec!["packed".to_string()]);
+        assert_eq!(info.uv_count_since_last_pin_entry, Some(0));
+        assert!(info.long_touch_for_reset);
+        assert_eq!(info.max_pin_length, 63);
+        assert_eq!(info.unknown[&0x40], Value::Bool(true));
+    }
+
+    #[test]
+    fn test_select_pre_commands() {
+        let mut info = get_info_params::Info {
+            versions: vec!["FIDO_2_0".to_string(), "FIDO_2_1_PRE".to_string()],
+            ..Default::default()
+        };
+        assert!(info.is_prototype("bioEnroll", "userVerificationMgmtPreview"));
+        assert!(info.is_prototype("credMgmt", "credentialMgmtPreview"));
+
+        info.versions.push("FIDO_2_1".to_string());
+        assert!(!info.is_prototype("bioEnroll", "userVerificationMgmtPreview"));
+        assert!(!info.is_prototype("credMgmt", "credentialMgmtPreview"));
+
+        // options take precedence over versions
+        info.options
+            .insert("userVerificationMgmtPreview".to_string(), true);
+        info.options
+            .insert("credentialMgmtPreview".to_string(), true);
+        assert!(info.is_prototype("bioEnroll", "userVerificationMgmtPreview"));
+        assert!(info.is_prototype("credMgmt", "credentialMgmtPreview"));
+
+        info.options.insert("bioEnroll".to_string(), false);
+        info.options.insert("credMgmt".to_string(), true);
+        assert!(!info.is_prototype("bioEnroll", "userVerificationMgmtPreview"));
+        assert!(!info.is_prototype("credMgmt", "credentialMgmtPreview"));
+    }
+
+    #[test]
+    fn test_select_user_verification_method() {
+        use client_pin_params::UserVerificationMethod as Method;
+
+        let info_of = |options: &[(&str, bool)]| get_info_params::Info {
+            options: options.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+            ..Default::default()
+        };
+        let select = |info: &get_info_params::Info, policy, pin, rk| {
+            client_pin::select_user_verification_method(info, policy, pin, rk).ok()
+        };
+
+        // PIN only
+        let info = info_of(&[("clientPin", true)]);
+        assert_eq!(
+            select(&info, UserVerification::Preferred, Some("1234"), None),
+            Some(Method::PinToken)
+        );
+        assert_eq!(
+            select(&info, UserVerification::Discouraged, Some("1234"), None),
+            Some(Method::NotRequested)
+        );
+        // makeCredential on a protected authenticator requires uv
+        assert_eq!(
+            select(
+                &info,
+                UserVerification::Discouraged,
+                Some("1234"),
+                Some(false)
+            ),
+            Some(Method::PinToken)
+        );
+        assert_eq!(select(&info, UserVerification::Required, None, None), None);
+
+        // makeCredUvNotRqd - non-discoverable credentials without uv
+        let info = info_of(&[("clientPin", true), ("makeCredUvNotRqd", true)]);
+        assert_eq!(
+            select(&info, UserVerification::Discouraged, None, Some(false)),
+            Some(Method::NotRequested)
+        );
+        assert_eq!(
+            select(
+                &info,
+                UserVerification::Discouraged,
+                Some("1234"),
+                Some(true)
+            ),
+            Some(Method::PinToken)
+        );
+
+        // built-in uv
+        let info = info_of(&[("uv", true), ("pinUvAuthToken", true)]);
+        assert_eq!(
+            select(&info, UserVerification::Required, None, None),
+            Some(Method::UvToken)
+        );
+        let info = info_of(&[("uv", true)]);
+        assert_eq!(
+            select(&info, UserVerification::Preferred, None, None),
+            Some(Method::UvOption)
+        );
+
+        // alwaysUv
+        let info = info_of(&[("clientPin", true), ("alwaysUv", true)]);
+        assert_eq!(
+            select(&info, UserVerification::Discouraged, Some("1234"), None),
+            Some(Method::PinToken)
+        );
+
+        // not protected
+        let info = info_of(&[]);
+        assert_eq!(
+            select(&info, UserVerification::Preferred, None, None),
+            Some(Method::NotRequested)
+        );
+        assert_eq!(select(&info, UserVerification::Required, None, None), None);
+    }
+
+    #[test]
+    fn test_is_pin_required() {
+        let info_of = |options: &[(&str, bool)]| get_info_params::Info {
+            options: options.iter().map(|(k, v)| (k.to_string(), *v)).collect(),
+            ..Default::default()
+        };
+
+        // built-in uv does not need the PIN
+        let info = info_of(&[("clientPin", true), ("uv", true), ("pinUvAuthToken", true)]);
+        assert!(!client_pin::is_pin_required(&info, UserVerification::Required, None).unwrap());
+
+        let info = info_of(&[("clientPin", true)]);
+        assert!(client_pin::is_pin_required(&info, UserVerification::Required, None).unwrap());
+        assert!(!client_pin::is_pin_required(&info, UserVerification::Discouraged, None).unwrap());
+
+        // not protected
+        let info = info_of(&[]);
+        assert!(client_pin::is_pin_required(&info, UserVerification::Required, None).is_err());
+    }
+
+    #[test]
+    fn test_pin_provider() {
+        use pin_provider::{EnvPin, FixedPin, PinContext};
+
+        let context = PinContext {
+            reason: PinReason::Verify,
+            retries: Some(8),
+            min_pin_length: 4,
+        };
+        assert_eq!(
+            FixedPin::new("1234")
+                .provide_pin(&context)
+                .unwrap()
+                .as_str(),
+            "1234"
+        );
+
+        let closure = |context: &PinContext| -> Result<Zeroizing<String>> {
+            Ok(Zeroizing::new(format!(
+                "{}-{}",
+                context.reason,
+                context.retries.unwrap_or(0)
+            )))
+        };
+        assert_eq!(closure.provide_pin(&context).unwrap().as_str(), "verify-8");
+
+        std::env::set_var("CTAP_HID_FIDO2_TEST_PIN", "5678");
+        let env = EnvPin::new("CTAP_HID_FIDO2_TEST_PIN");
+        assert_eq!(env.provide_pin(&context).unwrap().as_str(), "5678");
+        assert!(EnvPin::new("CTAP_HID_FIDO2_TEST_PIN_NOT_SET")
+            .provide_pin(&context)
+            .is_err());
+    }
+
+    #[test]
+    fn test_pin_retry_error() {
+        use client_pin_params::PinRetryError;
+        use serde_cbor::Value;
+        use std::collections::BTreeMap;
+
+        // getRetries response with powerCycleState
+        let mut map = BTreeMap::new();
+        map.insert(Value::Integer(0x03), Value::Integer(5));
+        map.insert(Value::Integer(0x04), Value::Bool(true));
+        let bytes = serde_cbor::to_vec(&Value::Map(map)).unwrap();
+        let pin = client_pin_response::parse_cbor_client_pin_get_retries(&bytes).unwrap();
+        assert_eq!(pin.retries, 5);
+        assert!(pin.power_cycle_state);
+
+        let err = PinRetryError::RetryFloorReached {
+            retries: 2,
+            floor: 2,
+        };
+        assert_eq!(err.retries(), Some(2));
+        assert!(!err.power_cycle_required());
+
+        let err = Error::new(PinRetryError::PinInvalid {
+            retries: Some(4),
+            power_cycle_required: true,
+        });
+        let err = err.downcast_ref::<PinRetryError>().unwrap();
+        assert_eq!(err.retries(), Some(4));
+        assert!(err.power_cycle_required());
+        assert_eq!(PinRetryError::PinBlocked.retries(), Some(0));
+    }
+
+    #[test]
+    fn test_large_blobs_set_pin_uv_auth_protocol() {
+        let param = |pin_token: &pintoken::PinToken| {
+            let payload =
+                large_blobs_command::create_payload_set(Some(pin_token), 0, Some(3), &[1, 2, 3]);
+            let map = match serde_cbor::from_slice(&payload[1..]).unwrap() {
+                serde_cbor::Value::Map(map) => map,
+                _ => panic!("not a map"),
+            };
+            (
+                map[&serde_cbor::Value::Integer(0x05)].clone(),
+                map[&serde_cbor::Value::Integer(0x06)].clone(),
+            )
+        };
+
+        let (auth, protocol) = param(&pintoken::PinToken::new(&[0xab; 32]));
+        assert!(matches!(auth, serde_cbor::Value::Bytes(b) if b.len() == 16));
+        assert_eq!(protocol, serde_cbor::Value::Integer(1));
+
+        let (auth, protocol) = param(&pintoken::PinToken::with_protocol(&[0xab; 32], 2));
+        assert!(matches!(auth, serde_cbor::Value::Bytes(b) if b.len() == 32));
+        assert_eq!(protocol, serde_cbor::Value::Integer(2));
+    }
+
+    #[test]
+    fn test_cose_key_subject_public_key_info() {
+        let ec2 = |crv: i128, len: usize| {
+            cose_key_fixture(
+                2,
+                -7,
+                vec![
+                    (-1, serde_cbor::Value::Integer(crv)),
+                    (-2, serde_cbor::Value::Bytes(vec![0x01; len])),
+                    (-3, serde_cbor::Value::Bytes(vec![0x02; len])),
+                ],
+            )
+        };
+
+        // P-256 : same as the fixed header
+        let p256 = cose::CoseKey::new(&ec2(1, 32)).unwrap();
+        let pem = util::convert_spki_to_pem(&p256.to_subject_public_key_info());
+        assert_eq!(
+            pem,
+            util::convert_to_publickey_pem(&p256.to_public_key_der())
+        );
+
+        // P-384 / P-521 : secp384r1 / secp521r1
+        let p384 = cose::CoseKey::new(&ec2(2, 48))
+            .unwrap()
+            .to_subject_public_key_info();
+        assert_eq!(
+            hex::encode(&p384[..23]),
+            "3076301006072a8648ce3d020106052b81040022036200"
+        );
+        assert_eq!(p384.len(), 23 + 97);
+        let p521 = cose::CoseKey::new(&ec2(3, 66))
+            .unwrap()
+            .to_subject_public_key_info();
+        assert_eq!(
+            hex::encode(&p521[..25]),
+            "30819b301006072a8648ce3d020106052b8104002303818600"
+        );
+        assert_eq!(p521.len(), 25 + 133);
+
+        // RSA : n(-1) and e(-2) are byte strings
+        let rsa = cose::CoseKey::new(&cose_key_fixture(
+            3,
+            -257,
+            vec![
+                (-1, serde_cbor::Value::Bytes(vec![0xc1; 256])),
+                (-2, serde_cbor::Value::Bytes(vec![0x01, 0x00, 0x01])),
+            ],
+        ))
+        .unwrap();
+        assert_eq!(rsa.key_type, 3);
+        let spki = rsa.to_subject_public_key_info();
+        assert_eq!(
+            hex::encode(&spki[..33]),
+            "30820122300d06092a864886f70d01010105000382010f003082010a0282010100"
+        );
+        assert_eq!(hex::encode(&spki[spki.len() - 5..]), "0203010001");
+        assert!(
+            util::convert_spki_to_pem(&spki).starts_with("-----BEGIN PUBLIC KEY-----\nMIIBIjAN")
+        );
+
+        // unknown curve
+        assert!(cose::CoseKey::new(&ec2(9, 32))
+            .unwrap()
+            .to_subject_public_key_info()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_get_assertion_allow_list_of_credential_id() {
+        // an empty credential_id asks for discoverable credentials
+        let args = GetAssertionArgsBuilder::new("test.com", b"challenge")
+            .credential_id(&[])
+            .build();
+        assert!(args.allow_list.is_empty());
+
+        let args = GetAssertionArgsBuilder::new("test.com", b"challenge")
+            .credential_id(b"credential-1")
+            .build();
+        assert_eq!(args.allow_list.len(), 1);
+        assert_eq!(args.allow_list[0].id, b"credential-1".to_vec());
+    }
+
+    #[test]
+    fn test_credential_probing() {
+        let list: Vec<PublicKeyCredentialDescriptor> = (1..=5)
+            .map(|n| PublicKeyCredentialDescriptor::new(&vec![n; n as usize * 10]))
+            .collect();
+
+        // maxCredentialIdLength drops the long IDs, maxCredentialCountInList sizes the batches
+        let info = get_info_params::Info {
+            max_credential_count_in_list: 2,
+            max_credential_id_length: 40,
+            ..Default::default()
+        };
+        let batches = get_assertion::probe_batches(&info, &list);
+        let lens: Vec<Vec<usize>> = batches
+            .iter()
+            .map(|batch| batch.iter().map(|x| x.id.len()).collect())
+            .collect();
+        assert_eq!(lens, vec![vec![10, 20], vec![30, 40]]);
+
+        assert!(!get_assertion::fits_in_one_request(&info, &batches));
+
+        // a list within maxCredentialCountInList is sent as it is
+        let batches = get_assertion::probe_batches(&info, &list[..2]);
+        assert!(get_assertion::fits_in_one_request(&info, &batches));
+        assert_eq!(batches.concat(), list[..2].to_vec());
+
+        // the credential found by the pre-flight, or the first batch if none was found
+        let batches = get_assertion::probe_batches(&info, &list);
+        assert_eq!(
+            get_assertion::credential_list_to_send(batches.clone(), Some(list[2].clone())),
+            vec![list[2].clone()]
+        );
+        assert_eq!(
+            get_assertion::credential_list_to_send(batches, None),
+            list[..2].to_vec()
+        );
+
+        // one by one if the authenticator does not report the limits,
+        // but the whole list is sent without a pre-flight
+        let info = get_info_params::Info::default();
+        let batches = get_assertion::probe_batches(&info, &list);
+        assert_eq!(batches.len(), 5);
+        assert!(get_assertion::fits_in_one_request(&info, &batches));
+
+        // the credential that matched
+        assert_eq!(
+            get_assertion::matched_credential(&list, &[3; 30]),
+            Some(list[2].clone())
+        );
+        assert_eq!(get_assertion::matched_credential(&list, &[9; 10]), None);
+        assert_eq!(
+            get_assertion::matched_credential(&list[..1], &[]),
+            Some(list[0].clone())
+        );
+        assert_eq!(get_assertion::matched_credential(&list, &[]), None);
+    }
+
+    #[test]
+    fn test_paging() {
+        let mut paging = paging::Paging::new(Some(1), 3);
+        assert_eq!(paging.total(), 3);
+        assert_eq!(paging.remaining(), 3);
+
+        // the first item is not fetched
+        let item = paging.next_with(|| panic!("first item fetched")).unwrap();
+        assert_eq!(item.unwrap(), 1);
+        assert_eq!(paging.remaining(), 2);
+
+        assert_eq!(paging.next_with(|| Ok(2)).unwrap().unwrap(), 2);
+        assert_eq!(paging.next_with(|| Ok(3)).unwrap().unwrap(), 3);
+        assert_eq!(paging.remaining(), 0);
+        assert!(paging.next_with(|| Ok(4)).is_none());
+
+        // stop after an error
+        let mut paging = paging::Paging::new(Some(1), 5);
+        paging.next_with(|| Ok(0)).unwrap().unwrap();
+        assert!(paging.next_with(|| Err(anyhow!("error"))).unwrap().is_err());
+        assert_eq!(paging.remaining(), 0);
+        assert!(paging.next_with(|| Ok(3)).is_none());
+
+        // nothing to enumerate
+        let mut paging = paging::Paging::<u32>::new(None, 5);
+        assert_eq!(paging.total(), 0);
+        assert!(paging.next_with(|| Ok(1)).is_none());
+    }
+
+    #[test]
+    fn test_get_next_assertion_timer() {
+        let mut last_response = std::time::Instant::now();
+        let before = last_response;
+        assert_eq!(
+            get_assertion::get_next_in_time(&mut last_response, || Ok(1)).unwrap(),
+            1
+        );
+        assert!(last_response >= before);
+
+        // the timer expired - authenticatorGetNextAssertion is not sent
+        if let Some(expired) =
+            std::time::Instant::now().checked_sub(std::time::Duration::from_secs(31))
+        {
+            let mut last_response = expired;
+            let ret = get_assertion::get_next_in_time(&mut last_response, || -> Result<u32> {
+                panic!("sent after the timer expired")
+            });
+            assert!(ret.is_err());
+            assert_eq!(last_response, expired);
+        }
+    }
+
+    #[test]
+    fn test_secrets_not_in_debug() {
+        let pin_token = pintoken::PinToken::new(&[0xab; 32]);
+        assert!(!format!("{:?}", pin_token).contains("171"));
+
+        let debug = format!("{:?}", ss::SharedSecret::default());
+        assert!(!debug.contains("secret:"));
+        assert!(!debug.contains("aes_key"));
+    }
+
+    #[test]
+    fn test_u2f_response() {
+        // reserved || user public key || key handle || attestation certificate || signature
+        let mut register = vec![0x05];
+        register.extend_from_slice(&[0x04; 65]);
+        register.push(3);
+        register.extend_from_slice(&[0xa1, 0xa2, 0xa3]);
+        let mut cert = vec![0x30, 0x81, 0x80];
+        cert.extend_from_slice(&[0x11; 0x80]);
+        register.extend_from_slice(&cert);
+        register.extend_from_slice(&[0x30, 0x02, 0x05, 0x00]);
+
+        let res = u2f_response::parse_register(&register).unwrap();
+        assert_eq!(res.user_public_key, vec![0x04; 65]);
+        assert_eq!(res.key_handle, vec![0xa1, 0xa2, 0xa3]);
+        assert_eq!(res.attestation_certificate, cert);
+        assert_eq!(res.signature, vec![0x30, 0x02, 0x05, 0x00]);
+        assert!(u2f_response::parse_register(&register[..100]).is_err());
+
+        let res = u2f_response::parse_authenticate(&[0x01, 0, 0, 0x01, 0x02, 0x30]).unwrap();
+        assert!(res.user_presence);
+        assert_eq!(res.counter, 0x0102);
+        assert_eq!(res.signature, vec![0x30]);
+
+        assert!(u2f_command::create_authenticate_data(&[0; 32], &[0; 32], &[]).is_err());
+        assert!(u2f_command::create_register_data(&[0; 31], &[0; 32]).is_err());
+        assert!(u2f_command::create_authenticate_data(&[0; 32], &[0; 32], &[0; 255]).is_ok());
+        assert!(u2f_command::create_authenticate_data(&[0; 32], &[0; 32], &[0; 256]).is_err());
+    }
+
+    #[test]
+    fn test_u2f_key_handles() {
+        let descriptor = |len: usize| PublicKeyCredentialDescriptor::new(&vec![0x01; len]);
+        let list = vec![
+            descriptor(300),
+            descriptor(0),
+            descriptor(64),
+            descriptor(255),
+        ];
+        let ids: Vec<usize> = u2f::key_handles(&list).map(|x| x.id.len()).collect();
+        assert_eq!(ids, vec![64, 255]);
+    }
+
+    #[test]
+    fn test_u2f_is_ctap1_only() {
+        // CAPABILITY_WINK
+        assert!(u2f::is_ctap1_only(0x01));
+        // CAPABILITY_WINK | CAPABILITY_CBOR
+        assert!(!u2f::is_ctap1_only(0x05));
+        // CAPABILITY_WINK | CAPABILITY_CBOR | CAPABILITY_NMSG
+        assert!(!u2f::is_ctap1_only(0x0D));
+    }
+
+    #[test]
+    fn test_u2f_cose_key() {
+        let mut user_public_key = vec![0x04];
+        user_public_key.extend_from_slice(&[0x11; 32]);
+        user_public_key.extend_from_slice(&[0x22; 32]);
+
+        let data = auth_data::AttestedCredentialData {
+            aaguid: vec![0; 16],
+            credential_id: vec![0xa1, 0xa2],
+            credential_public_key: u2f::cose_es256_key(&user_public_key).unwrap(),
+        };
+        assert_eq!(
+            data.credential_public_key,
+            es256_cose_key_fixture(&[0x11; 32], &[0x22; 32])
+        );
+        assert_eq!(data.algorithm().unwrap(), -7);
+        assert_eq!(data.public_key().unwrap().der, user_public_key);
+
+        assert!(u2f::cose_es256_key(&user_public_key[1..]).is_err());
+    }
+
+    #[test]
+    fn test_credential_management_credential() {
+        use serde_cbor::Value;
+        use std::collections::BTreeMap;
+
+        // COSE_Key of an ES256 public key (0x04 || x || y)
+        let mut user_public_key = vec![0x04];
+        user_public_key.extend_from_slice(&[0x11; 64]);
+        let cose_key = es256_cose_key_fixture(&[0x11; 32], &[0x11; 32]);
+
+        let mut map = BTreeMap::new();
+        map.insert(Value::Integer(0x08), cose_key.clone());
+        map.insert(Value::Integer(0x09), Value::Integer(2));
+        map.insert(Value::Integer(0x0A), Value::Integer(3));
+        map.insert(Value::Integer(0x0B), Value::Bytes(vec![0xab; 32]));
+        map.insert(Value::Integer(0x0C), Value::Bool(true));
+        let cbor = serde_cbor::to_vec(&Value::Map(map)).unwrap();
+
+        let data = credential_management_response::parse_cbor(&cbor).unwrap();
+        assert_eq!(data.total_credentials, 2);
+
+        let rp = credential_management_params::Rp {
+            rpid_hash: vec![0x01; 32],
+            ..Default::default()
+        };
+        let credential = credential_management_params::Credential::new(&data).with_rp(&rp);
+        assert_eq!(credential.credential_public_key, Some(cose_key));
+        assert_eq!(credential.public_key.der, user_public_key);
+        assert_eq!(credential.large_blob_key, vec![0xab; 32]);
+        assert_eq!(credential.third_party_payment, Some(true));
+        assert_eq!(credential.rpid_hash, vec![0x01; 32]);
+        assert!(matches!(
+            credential.cred_protect,
+            credential_management_params::CredentialProtectionPolicy::UserVerificationRequired
+        ));
+    }
+
+    // canned responses of the enumeration subcommands, and the subcommands sent
+    struct CannedEnumerator {
+        responses: Vec<serde_cbor::Value>,
+        sent: std::sync::Arc<std::sync::Mutex<Vec<credential_management_command::SubCommand>>>,
+    }
+
+    impl credential_management::Enumerator for CannedEnumerator {
+        fn send(
+            &mut self,
+            sub_command: credential_management_command::SubCommand,
+            _rpid_hash: Option<Vec<u8>>,
+        ) -> std::result::Result<
+            credential_management_params::CredentialManagementData,
+            ctaphid::CtapHidError,
+        > {
+            self.sent.lock().unwrap().push(sub_command);
+            match self.responses.remove(0) {
+                // CTAP status of an error response
+                serde_cbor::Value::Integer(status) => Err(ctaphid::CtapHidError {
+                    status: Some(status as u8),
+                    message: ctapdef::get_ctap_status_message(status as u8),
+                }),
+                response => {
+                    let cbor = serde_cbor::to_vec(&response).unwrap();
+                    Ok(credential_management_response::parse_cbor(&cbor).unwrap())
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_credential_management_iter() {
+        use credential_management_command::SubCommand;
+        use serde_cbor::Value;
+        use std::sync::{Arc, Mutex};
+
+        let response = |members: Vec<(i128, Value)>| {
+            Value::Map(
+                members
+                    .into_iter()
+                    .map(|(member, val)| (Value::Integer(member), val))
+                    .collect(),
+            )
+        };
+        let canned = |responses: Vec<Value>| {
+            let sent = Arc::new(Mutex::new(vec![]));
+            let enumerator = Box::new(CannedEnumerator {
+                responses,
+                sent: sent.clone(),
+            });
+            (enumerator, sent)
+        };
+
+        // rpIDHash(0x04), totalRPs(0x05) - an extra response that must not be fetched
+        let (enumerator, sent) = canned(
+            (1..=4)
+                .map(|n| {
+                    let mut members = vec![(0x04, Value::Bytes(vec![n; 32]))];
+                    if n == 1 {
+                        members.push((0x05, Value::Integer(3)));
+                    }
+                    response(members)
+                })
+                .collect(),
+        );
+        let mut rps = credential_management::enumerate_rps(enumerator).unwrap();
+        assert_eq!(rps.total_rps(), 3);
+        let rpid_hashes: Vec<Vec<u8>> = rps.by_ref().map(|rp| rp.unwrap().rpid_hash).collect();
+        assert_eq!(rpid_hashes, vec![vec![1; 32], vec![2; 32], vec![3; 32]]);
+        assert!(rps.next().is_none());
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![
+                SubCommand::EnumerateRPsBegin,
+                SubCommand::EnumerateRPsGetNextRp,
+                SubCommand::EnumerateRPsGetNextRp,
+            ]
+        );
+
+        // largeBlobKey(0x0B), totalCredentials(0x09)
+        // - enumerateCredentialsGetNextCredential fails, the enumeration stops
+        let rp = credential_management_params::Rp {
+            rpid_hash: vec![0x01; 32],
+            ..Default::default()
+        };
+        let (enumerator, sent) = canned(vec![
+            response(vec![
+                (0x0B, Value::Bytes(vec![1; 32])),
+                (0x09, Value::Integer(3)),
+            ]),
+            Value::Integer(0x30),
+            response(vec![(0x0B, Value::Bytes(vec![3; 32]))]),
+        ]);
+        let mut credentials =
+            credential_management::enumerate_credentials(enumerator, &rp).unwrap();
+        assert_eq!(credentials.total_credentials(), 3);
+        let credential = credentials.next().unwrap().unwrap();
+        assert_eq!(credential.large_blob_key, vec![1; 32]);
+        assert_eq!(credential.rpid_hash, vec![0x01; 32]);
+        assert!(credentials.next().unwrap().is_err());
+        assert!(credentials.next().is_none());
+        assert_eq!(
+            *sent.lock().unwrap(),
+            vec![
+                SubCommand::EnumerateCredentialsBegin,
+                SubCommand::EnumerateCredentialsGetNextCredential,
+            ]
+        );
+
+        // CTAP2_ERR_NO_CREDENTIALS - nothing to enumerate
+        let (enumerator, sent) = canned(vec![Value::Integer(0x2E)]);
+        let mut rps = credential_management::enumerate_rps(enumerator).unwrap();
+        assert_eq!(rps.total_rps(), 0);
+        assert!(rps.next().is_none());
+        assert_eq!(*sent.lock().unwrap(), vec![SubCommand::EnumerateRPsBegin]);
+    }
+
+    #[test]
+    fn test_persistent_token() {
+        use credential_management_params::PersistentPinUvAuthToken;
+
+        let token = PersistentPinUvAuthToken::new(1, &[0xab; 32]).unwrap();
+        assert_eq!(token.key(), &[0xab; 32]);
+        assert_eq!(token.pin_uv_auth_protocol(), 1);
+        assert!(!format!("{:?}", token).contains("171"));
+
+        // only pinUvAuthProtocol 1 with a 32-byte key
+        assert!(PersistentPinUvAuthToken::new(2, &[0xab; 32]).is_err());
+        assert!(PersistentPinUvAuthToken::new(1, &[0xab; 16]).is_err());
+
+        // getPinUvAuthTokenUsingPinWithPermissions - permissions(0x09) is pcmr alone
+        let permissions = |send_payload: Vec<u8>| {
+            util::cbor_bytes_to_map(&send_payload[1..])
+                .unwrap()
+                .get(&serde_cbor::Value::Integer(0x09))
+                .cloned()
+        };
+        let peer_key = peer_key_fixture();
+        let send_payload =
+            client_pin_command::create_payload_get_pin_uv_auth_token_using_pin_with_permissions(
+                &peer_key,
+                &[0x01; 16],
+                &[client_pin_command::Permission::Pcmr],
+            );
+        assert_eq!(
+            permissions(send_payload),
+            Some(serde_cbor::Value::Integer(0x40))
+        );
+
+        // mc and ga for the excludeList pre-flight
+        let send_payload =
+            client_pin_command::create_payload_get_pin_uv_auth_token_using_uv_with_permissions(
+                &peer_key,
+                &[
+                    client_pin_command::Permission::Mc,
+                    client_pin_command::Permission::Ga,
+                ],
+                "test.com",
+            );
+        assert_eq!(
+            permissions(send_payload),
+            Some(serde_cbor::Value::Integer(0x03))
+        );
+    }
 }
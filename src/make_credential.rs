@@ -1,31 +1,192 @@
 use crate::client_pin;
+use crate::client_pin_command::Permission;
+use crate::client_pin_params::{UserVerification, UserVerificationMethod};
 use crate::ctaphid;
 use crate::enc_hmac_sha_256;
+use crate::get_assertion;
+use crate::get_info;
+use crate::get_info_params::Info;
+use crate::hmac::HmacExt;
 use crate::make_credential_command;
 use crate::make_credential_params;
-use crate::make_credential_params::{CredentialSupportedKeyType, Extension};
+use crate::make_credential_params::{
+    CredentialExcluded, CredentialSupportedKeyType, EnterpriseAttestation, Extension,
+};
 use crate::make_credential_response;
+use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
 use crate::public_key_credential_user_entity::PublicKeyCredentialUserEntity;
+use crate::u2f;
 use crate::FidoKeyHid;
 
 #[allow(unused_imports)]
 use crate::util;
 
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
+
+// authenticatorMakeCredential request
+// - rk is set with rkparam
+#[derive(Debug, Default)]
+pub struct Params<'a> {
+    pub rpid: &'a str,
+    pub challenge: &'a [u8],
+    pub pin: Option<&'a str>,
+    pub rkparam: Option<&'a PublicKeyCredentialUserEntity>,
+    pub uv: Option<bool>,
+    pub user_verification: Option<UserVerification>,
+    pub extensions: Option<&'a Vec<Extension>>,
+    pub key_types: &'a [CredentialSupportedKeyType],
+    pub exclude_list: &'a [PublicKeyCredentialDescriptor],
+    pub enterprise_attestation: Option<EnterpriseAttestation>,
+}
 
 pub fn make_credential(
     device: &FidoKeyHid,
-    rpid: &str,
-    challenge: &[u8],
-    pin: Option<&str>,
-    rk: bool,
-    rkparam: Option<&PublicKeyCredentialUserEntity>,
-    uv: Option<bool>,
-    extensions: Option<&Vec<Extension>>,
-    key_type: Option<CredentialSupportedKeyType>,
+    params: &Params,
 ) -> Result<make_credential_params::Attestation> {
+    let Params {
+        rpid,
+        challenge,
+        pin,
+        rkparam,
+        uv,
+        user_verification,
+        extensions,
+        key_types,
+        exclude_list,
+        enterprise_attestation,
+    } = *params;
+    let rk = rkparam.is_some();
+
     // init
-    let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+    let (cid, capabilities) =
+        ctaphid::ctaphid_init_with_capabilities(device).map_err(Error::msg)?;
+
+    // CTAP1/U2F authenticator
+    // - the implicit uv (without a PIN) is not requested
+    if u2f::is_ctap1_only(capabilities) {
+        if rk
+            || enterprise_attestation.is_some()
+            || user_verification == Some(UserVerification::Required)
+            || pin.is_some()
+        {
+            return Err(anyhow!(
+                "CTAP1/U2F authenticator does not support rk, user verification or enterprise attestation."
+            ));
+        }
+        if extensions.is_some_and(|exts| !exts.is_empty()) {
+            return Err(anyhow!(
+                "CTAP1/U2F authenticator does not support extensions."
+            ));
+        }
+        if !key_types.is_empty() && !key_types.contains(&CredentialSupportedKeyType::Ecdsa256) {
+            return Err(anyhow!(
+                "CTAP1/U2F authenticator supports only ES256. ({:?})",
+                key_types
+            ));
+        }
+        return u2f::make_credential(device, rpid, challenge, exclude_list);
+    }
+
+    // credBlob / hmac-secret-mc
+    let cred_blob = extensions.and_then(|exts| {
+        exts.iter().find_map(|ext| match ext {
+            Extension::CredBlob((Some(cred_blob), _)) => Some(cred_blob),
+            _ => None,
+        })
+    });
+    let hmac_secret_mc = extensions.and_then(|exts| {
+        exts.iter().find_map(|ext| match ext {
+            Extension::HmacSecretMc((salt1, salt2)) => Some((*salt1, *salt2)),
+            _ => None,
+        })
+    });
+
+    // authenticatorGetInfo - fetched once if any of the parameters depends on it
+    let info = if enterprise_attestation.is_some()
+        || !key_types.is_empty()
+        || cred_blob.is_some()
+        || hmac_secret_mc.is_some()
+        || !exclude_list.is_empty()
+        || user_verification.is_some()
+    {
+        Some(get_info::get_info(device)?)
+    } else {
+        None
+    };
+
+    let mut pin_uv_auth_protocol = 1;
+    if let Some(info) = &info {
+        // enterpriseAttestation
+        if enterprise_attestation.is_some() {
+            check_enterprise_attestation(info)?;
+        }
+
+        // credBlob
+        if let Some(cred_blob) = cred_blob {
+            check_cred_blob(info, cred_blob)?;
+        }
+
+        // hmac-secret-mc
+        if hmac_secret_mc.is_some() {
+            pin_uv_auth_protocol = check_hmac_secret_mc(info)?;
+        }
+    }
+
+    // pubKeyCredParams
+    let key_types = match &info {
+        Some(info) if !key_types.is_empty() => filter_key_types(info, key_types)?,
+        _ => vec![CredentialSupportedKeyType::Ecdsa256],
+    };
+
+    // user verification
+    let uv_method = match (user_verification, &info) {
+        (Some(policy), Some(info)) => {
+            client_pin::select_user_verification_method(info, policy, pin, Some(rk))?
+        }
+        _ => client_pin::user_verification_method_of(pin, uv),
+    };
+    let uv = if user_verification.is_some() {
+        (uv_method == UserVerificationMethod::UvOption).then_some(true)
+    } else {
+        uv
+    };
+
+    // pin token
+    // - the ga permission for the excludeList pre-flight
+    let permissions: &[Permission] = if exclude_list.is_empty() {
+        &[Permission::Mc]
+    } else {
+        &[Permission::Mc, Permission::Ga]
+    };
+    let pin_token =
+        client_pin::get_user_verification_token(device, &cid, uv_method, pin, permissions, rpid)?;
+
+    // excludeList
+    let exclude_list = match &info {
+        Some(info) if !exclude_list.is_empty() => get_assertion::select_credential_list(
+            device,
+            &cid,
+            rpid,
+            info,
+            exclude_list,
+            pin_token.as_ref(),
+        )?,
+        _ => exclude_list.to_vec(),
+    };
+    let excluded_credential_id = if exclude_list.len() == 1 {
+        Some(exclude_list[0].id.to_vec())
+    } else {
+        None
+    };
+
+    let hmac_ext = if let Some((salt1, salt2)) = hmac_secret_mc {
+        let salt1 = salt1.ok_or_else(|| anyhow!("hmac-secret-mc salt1 is missing."))?;
+        let mut hmac_ext = HmacExt::default();
+        hmac_ext.create(device, &cid, &salt1, salt2.as_ref(), pin_uv_auth_protocol)?;
+        Some(hmac_ext)
+    } else {
+        None
+    };
 
     let user_id = {
         if let Some(rkp) = rkparam {
@@ -40,28 +201,97 @@ pub fn make_credential(
         let mut params = make_credential_command::Params::new(rpid, challenge.to_vec(), user_id);
         params.option_rk = rk;
         params.option_uv = uv;
-        params.key_type = key_type.unwrap_or(CredentialSupportedKeyType::Ecdsa256);
+        params.key_types = key_types;
+        params.exclude_list = exclude_list;
+        params.enterprise_attestation = enterprise_attestation;
 
         if let Some(rkp) = rkparam {
             params.user_name = rkp.name.to_string();
             params.user_display_name = rkp.display_name.to_string();
         }
 
-        // get pintoken & create pin auth
-        if let Some(pin) = pin {
-            if !pin.is_empty() {
-                let pin_token = client_pin::get_pin_token(device, &cid, pin)?;
-                let sig = enc_hmac_sha_256::authenticate(&pin_token.key, &params.client_data_hash);
-                params.pin_auth = sig[0..16].to_vec();
-            }
+        // create pin auth
+        if let Some(pin_token) = pin_token {
+            let sig = enc_hmac_sha_256::authenticate(&pin_token.key, &params.client_data_hash);
+            params.pin_auth = sig[0..16].to_vec();
         }
 
-        make_credential_command::create_payload(params, extensions)
+        make_credential_command::create_payload(params, extensions, hmac_ext.as_ref())
     };
 
     // send & response
-    let response_cbor = ctaphid::ctaphid_cbor(device, &cid, &send_payload).map_err(Error::msg)?;
+    let response_cbor = match ctaphid::ctaphid_cbor(device, &cid, &send_payload) {
+        Ok(response_cbor) => response_cbor,
+        Err(err) if err.is_status(0x19) => {
+            return Err(Error::new(CredentialExcluded {
+                credential_id: excluded_credential_id,
+            }));
+        }
+        Err(err) => return Err(Error::msg(err)),
+    };
 
-    let att = make_credential_response::parse_cbor(&response_cbor).map_err(Error::msg)?;
+    let mut att = make_credential_response::parse_cbor(&response_cbor, hmac_ext.as_ref())
+        .map_err(Error::msg)?;
+    att.user_verification_method = uv_method;
     Ok(att)
 }
+
+fn check_enterprise_attestation(info: &Info) -> Result<()> {
+    match info.option("ep") {
+        Some(true) => Ok(()),
+        Some(false) => Err(anyhow!(
+            "Enterprise attestation is disabled. (enable it with authenticatorConfig)"
+        )),
+        None => Err(anyhow!("Enterprise attestation is not supported.")),
+    }
+}
+
+fn check_cred_blob(info: &Info, cred_blob: &[u8]) -> Result<()> {
+    let ext = Extension::CredBlob((None, None)).to_string();
+    if !info.extensions.contains(&ext) {
+        return Err(anyhow!("credBlob is not supported."));
+    }
+    if cred_blob.len() > info.max_cred_blob_length as usize {
+        return Err(anyhow!(
+            "credBlob is too long. ({} > maxCredBlobLength:{})",
+            cred_blob.len(),
+            info.max_cred_blob_length
+        ));
+    }
+    Ok(())
+}
+
+// returns the pinUvAuthProtocol used for hmac-secret-mc
+fn check_hmac_secret_mc(info: &Info) -> Result<u32> {
+    let ext = Extension::HmacSecretMc((None, None)).to_string();
+    if !info.extensions.contains(&ext) {
+        return Err(anyhow!("hmac-secret-mc is not supported."));
+    }
+    Ok(client_pin::select_pin_uv_auth_protocol(info))
+}
+
+// Remove the algorithms that are not in authenticatorGetInfo algorithms(0x0A).
+// If the authenticator does not report algorithms, the list is sent as it is.
+fn filter_key_types(
+    info: &Info,
+    key_types: &[CredentialSupportedKeyType],
+) -> Result<Vec<CredentialSupportedKeyType>> {
+    let algorithms: Vec<i32> = info.algorithms.iter().map(|param| param.alg).collect();
+    if algorithms.is_empty() {
+        return Ok(key_types.to_vec());
+    }
+
+    let filtered: Vec<CredentialSupportedKeyType> = key_types
+        .iter()
+        .filter(|&&key_type| algorithms.contains(&(key_type as i32)))
+        .copied()
+        .collect();
+    if filtered.is_empty() {
+        return Err(anyhow!(
+            "None of the algorithms {:?} is supported. (algorithms:{:?})",
+            key_types,
+            algorithms
+        ));
+    }
+    Ok(filtered)
+}
@@ -1,5 +1,7 @@
 use crate::ctapdef;
-use crate::make_credential_params::{CredentialSupportedKeyType, Extension};
+use crate::hmac::HmacExt;
+use crate::make_credential_params::{CredentialSupportedKeyType, EnterpriseAttestation, Extension};
+use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
 use crate::util;
 use serde_cbor::to_vec;
 use serde_cbor::Value;
@@ -17,7 +19,10 @@ pub struct Params {
     pub option_uv: Option<bool>,
     pub client_data_hash: Vec<u8>,
     pub pin_auth: Vec<u8>,
-    pub key_type: CredentialSupportedKeyType,
+    // in order of preference
+    pub key_types: Vec<CredentialSupportedKeyType>,
+    pub exclude_list: Vec<PublicKeyCredentialDescriptor>,
+    pub enterprise_attestation: Option<EnterpriseAttestation>,
 }
 
 impl Params {
@@ -26,13 +31,17 @@ impl Params {
             rp_id: rp_id.to_string(),
             user_id: user_id.to_vec(),
             client_data_hash: util::create_clientdata_hash(challenge),
-            key_type: CredentialSupportedKeyType::Ecdsa256,
+            key_types: vec![CredentialSupportedKeyType::Ecdsa256],
             ..Default::default()
         }
     }
 }
 
-pub fn create_payload(params: Params, extensions: Option<&Vec<Extension>>) -> Vec<u8> {
+pub fn create_payload(
+    params: Params,
+    extensions: Option<&Vec<Extension>>,
+    hmac_ext: Option<&HmacExt>,
+) -> Vec<u8> {
     // 0x01 : clientDataHash
     let cdh = Value::Bytes(params.client_data_hash);
 
@@ -89,34 +98,75 @@ pub fn create_payload(params: Params, extensions: Option<&Vec<Extension>>) -> Ve
     let user = Value::Map(user_val);
 
     // 0x04 : pubKeyCredParams
-    let mut pub_key_cred_params_val = BTreeMap::new();
-    pub_key_cred_params_val.insert(
-        Value::Text("alg".to_string()),
-        Value::Integer(params.key_type as i128),
+    let pub_key_cred_params = Value::Array(
+        params
+            .key_types
+            .iter()
+            .map(|key_type| {
+                let mut pub_key_cred_params_val = BTreeMap::new();
+                pub_key_cred_params_val.insert(
+                    Value::Text("alg".to_string()),
+                    Value::Integer(*key_type as i128),
+                );
+                pub_key_cred_params_val.insert(
+                    Value::Text("type".to_string()),
+                    Value::Text("public-key".to_string()),
+                );
+                Value::Map(pub_key_cred_params_val)
+            })
+            .collect(),
     );
-    pub_key_cred_params_val.insert(
-        Value::Text("type".to_string()),
-        Value::Text("public-key".to_string()),
-    );
-    let tmp = Value::Map(pub_key_cred_params_val);
-    let pub_key_cred_params = Value::Array(vec![tmp]);
+
+    // 0x05 : excludeList
+    let exclude_list = if !params.exclude_list.is_empty() {
+        let exclude_list = params.exclude_list.iter().map(|x| x.to_value()).collect();
+        Some(Value::Array(exclude_list))
+    } else {
+        None
+    };
 
     // 0x06 : extensions
     let extensions = if let Some(extensions) = extensions {
         let mut map = BTreeMap::new();
         for ext in extensions {
-            match *ext {
+            match ext {
                 Extension::CredProtect(n) => {
                     map.insert(
                         Value::Text(ext.to_string()),
                         Value::Integer(n.unwrap() as i128),
                     );
                 }
-                Extension::CredBlob(_) => (),
-                Extension::MinPinLength(_) => (),
+                Extension::CredBlob((n, _)) => {
+                    if let Some(cred_blob) = n {
+                        map.insert(
+                            Value::Text(ext.to_string()),
+                            Value::Bytes(cred_blob.to_vec()),
+                        );
+                    }
+                }
+                Extension::MinPinLength((n, _)) => {
+                    if let Some(min_pin_length) = n {
+                        map.insert(Value::Text(ext.to_string()), Value::Bool(*min_pin_length));
+                    }
+                }
                 Extension::HmacSecret(n) => {
                     map.insert(Value::Text(ext.to_string()), Value::Bool(n.unwrap()));
                 }
+                Extension::LargeBlobKey(n) => {
+                    if let Some(large_blob_key) = n {
+                        map.insert(Value::Text(ext.to_string()), Value::Bool(*large_blob_key));
+                    }
+                }
+                Extension::HmacSecretMc(_) => {
+                    // hmac-secret-mc is sent together with "hmac-secret": true
+                    map.insert(
+                        Value::Text(Extension::HmacSecret(None).to_string()),
+                        Value::Bool(true),
+                    );
+                    if let Some(hmac_ext) = hmac_ext {
+                        map.insert(Value::Text(ext.to_string()), hmac_ext.to_value());
+                    }
+                }
             };
         }
         Some(Value::Map(map))
@@ -165,6 +215,9 @@ pub fn create_payload(params: Params, extensions: Option<&Vec<Extension>>) -> Ve
     make_credential.insert(Value::Integer(0x02), rp);
     make_credential.insert(Value::Integer(0x03), user);
     make_credential.insert(Value::Integer(0x04), pub_key_cred_params);
+    if let Some(x) = exclude_list {
+        make_credential.insert(Value::Integer(0x05), x);
+    }
     if let Some(x) = extensions {
         make_credential.insert(Value::Integer(0x06), x);
     }
@@ -173,6 +226,10 @@ pub fn create_payload(params: Params, extensions: Option<&Vec<Extension>>) -> Ve
         make_credential.insert(Value::Integer(0x08), x);
         make_credential.insert(Value::Integer(0x09), pin_protocol);
     }
+    // 0x0A : enterpriseAttestation
+    if let Some(x) = params.enterprise_attestation {
+        make_credential.insert(Value::Integer(0x0A), Value::Integer(x as i128));
+    }
     let cbor = Value::Map(make_credential);
 
     // Command - authenticatorMakeCredential (0x01)
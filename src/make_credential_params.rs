@@ -2,10 +2,14 @@
 make_credential API parameters
 */
 
+use crate::auth_data::{AuthenticatorData, Flags};
+use crate::client_pin_params::UserVerificationMethod;
 use crate::credential_management_params::CredentialProtectionPolicy;
+use crate::prf_params::PrfOutputs;
 use crate::public_key::PublicKey;
 use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
 use crate::str_buf::StrBuf;
+use crate::util;
 use std::fmt;
 use strum_macros::AsRefStr;
 
@@ -15,16 +19,24 @@ use strum_macros::AsRefStr;
 pub struct Attestation {
     pub fmt: String,
     pub rpid_hash: Vec<u8>,
-    pub flags_user_present_result: bool,
-    pub flags_user_verified_result: bool,
-    pub flags_attested_credential_data_included: bool,
-    pub flags_extension_data_included: bool,
+    pub flags: Flags,
     pub sign_count: u32,
     pub aaguid: Vec<u8>,
     pub credential_descriptor: PublicKeyCredentialDescriptor,
     pub credential_publickey: PublicKey,
     pub extensions: Vec<Extension>,
     pub auth_data: Vec<u8>,
+    /// parsed auth_data
+    pub authenticator_data: AuthenticatorData,
+    pub large_blob_key: Vec<u8>,
+    /// epAtt - whether an enterprise attestation was returned
+    pub ep_att: bool,
+    /// COSE algorithm of the generated credential public key
+    pub credential_algorithm: i32,
+    /// WebAuthn prf extension outputs (only if prf was requested)
+    pub prf: Option<PrfOutputs>,
+    /// how the user was verified
+    pub user_verification_method: UserVerificationMethod,
 
     pub attstmt_alg: i32,
     pub attstmt_sig: Vec<u8>,
@@ -36,25 +48,22 @@ impl fmt::Display for Attestation {
         let mut strbuf = StrBuf::new(42);
         strbuf
             .appenh("- rpid_hash", &self.rpid_hash)
-            .append(
-                "- flags_user_present_result",
-                &self.flags_user_present_result,
-            )
-            .append(
-                "- flags_attested_credential_data_included",
-                &self.flags_attested_credential_data_included,
-            )
-            .append(
-                "- flags_extension_data_included",
-                &self.flags_extension_data_included,
-            )
             .append("- sign_count", &self.sign_count)
+            .add(&format!("{}", &self.flags))
             .appenh("- aaguid", &self.aaguid)
             .append("- credential_descriptor", &self.credential_descriptor)
             .append("- credential_publickey", &self.credential_publickey)
+            .append("- credential_algorithm", &self.credential_algorithm)
             .append("- attstmt_alg", &self.attstmt_alg)
             .appenh("- attstmt_sig", &self.attstmt_sig)
-            .append("- attstmt_x5c_num", &self.attstmt_x5c.len());
+            .append("- attstmt_x5c_num", &self.attstmt_x5c.len())
+            .appenh("- large_blob_key", &self.large_blob_key)
+            .append("- ep_att", &self.ep_att)
+            .append("- user_verification_method", &self.user_verification_method);
+
+        if let Some(prf) = &self.prf {
+            strbuf.appent("- prf").add(&format!("{}", prf));
+        }
 
         for ex in &self.extensions {
             strbuf.append("- extension", &format!("{:?}", ex));
@@ -69,17 +78,26 @@ pub enum Extension {
     #[strum(serialize = "credProtect")]
     CredProtect(Option<CredentialProtectionPolicy>),
     #[strum(serialize = "credBlob")]
-    CredBlob(Option<Vec<u8>>), //  "credBlob": Byte String containing the credBlob value
+    CredBlob((Option<Vec<u8>>, Option<bool>)), // input: "credBlob": Byte String containing the credBlob value / output: stored or not
     #[strum(serialize = "minPinLength")]
-    MinPinLength(Option<bool>), // "minPinLength": true
+    MinPinLength((Option<bool>, Option<u32>)), // input: "minPinLength": true / output: current minimum PIN length
     #[strum(serialize = "hmac-secret")]
     HmacSecret(Option<bool>),
+    #[strum(serialize = "hmac-secret-mc")]
+    HmacSecretMc((Option<[u8; 32]>, Option<[u8; 32]>)), // input: salt1, salt2 / output: output1, output2
+    #[strum(serialize = "largeBlobKey")]
+    LargeBlobKey(Option<bool>), // "largeBlobKey": true
 }
 
-#[derive(Debug, Copy, Clone)]
+/// COSE algorithm of pubKeyCredParams
+/// [COSE Algorithms](https://www.iana.org/assignments/cose/cose.xhtml#algorithms)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum CredentialSupportedKeyType {
     Ecdsa256 = -7,
     Ed25519 = -8,
+    Ecdsa384 = -35,
+    Ecdsa512 = -36,
+    Rs256 = -257,
 }
 
 impl std::default::Default for CredentialSupportedKeyType {
@@ -87,3 +105,48 @@ impl std::default::Default for CredentialSupportedKeyType {
         Self::Ecdsa256
     }
 }
+
+impl CredentialSupportedKeyType {
+    pub fn from_i32(alg: i32) -> Option<Self> {
+        match alg {
+            -7 => Some(Self::Ecdsa256),
+            -8 => Some(Self::Ed25519),
+            -35 => Some(Self::Ecdsa384),
+            -36 => Some(Self::Ecdsa512),
+            -257 => Some(Self::Rs256),
+            _ => None,
+        }
+    }
+}
+
+/// enterpriseAttestation
+/// [7.1. Enterprise Attestation](https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-20210615.html#sctn-feature-descriptions-enterp-attstn)
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum EnterpriseAttestation {
+    /// The authenticator decides whether the RP ID is eligible (vendor-facilitated)
+    VendorFacilitated = 1,
+    /// The platform has decided that the RP ID is eligible (platform-managed)
+    PlatformManaged = 2,
+}
+
+/// CTAP2_ERR_CREDENTIAL_EXCLUDED(0x19) - a credential in the excludeList already exists on the authenticator
+#[derive(Debug, Clone, PartialEq)]
+pub struct CredentialExcluded {
+    /// The excluded credential, if it was identified by silent probing
+    pub credential_id: Option<Vec<u8>>,
+}
+
+impl fmt::Display for CredentialExcluded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Credential excluded: a credential in the excludeList already exists on the authenticator."
+        )?;
+        if let Some(credential_id) = &self.credential_id {
+            write!(f, " (credential_id:{})", util::to_hex_str(credential_id))?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for CredentialExcluded {}
@@ -0,0 +1,48 @@
+use anyhow::Result;
+
+// Items of a *Begin / *GetNext enumeration
+// - the first item comes with the response of the first command,
+//   the others are fetched one by one
+// - the enumeration stops after total items or at the first error
+pub(crate) struct Paging<T> {
+    first: Option<T>,
+    total: u32,
+    returned: u32,
+}
+
+impl<T> Paging<T> {
+    // nothing to enumerate without the first item
+    pub(crate) fn new(first: Option<T>, total: u32) -> Self {
+        let total = if first.is_some() { total.max(1) } else { 0 };
+        Paging {
+            first,
+            total,
+            returned: 0,
+        }
+    }
+
+    pub(crate) fn total(&self) -> u32 {
+        self.total
+    }
+
+    pub(crate) fn remaining(&self) -> u32 {
+        self.total - self.returned
+    }
+
+    pub(crate) fn next_with(&mut self, get_next: impl FnOnce() -> Result<T>) -> Option<Result<T>> {
+        if self.returned >= self.total {
+            return None;
+        }
+        self.returned += 1;
+
+        if let Some(item) = self.first.take() {
+            return Some(Ok(item));
+        }
+
+        let ret = get_next();
+        if ret.is_err() {
+            self.returned = self.total;
+        }
+        Some(ret)
+    }
+}
@@ -0,0 +1,122 @@
+/*!
+PIN provider - the PIN is asked for only when the authenticator needs it
+*/
+
+use anyhow::{anyhow, Result};
+use std::fmt;
+
+/// The PIN is returned wiped on drop
+pub use zeroize::Zeroizing;
+
+/// Why the PIN is needed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PinReason {
+    /// the current PIN (to get a pinUvAuthToken)
+    Verify,
+    /// a new PIN for an authenticator without a PIN
+    Set,
+    /// a new PIN to replace the current one (change PIN / forcePINChange)
+    Change,
+}
+
+impl fmt::Display for PinReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let reason = match self {
+            PinReason::Verify => "verify",
+            PinReason::Set => "set",
+            PinReason::Change => "change",
+        };
+        write!(f, "{}", reason)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PinContext {
+    pub reason: PinReason,
+    /// PIN retries left (Verify)
+    pub retries: Option<u32>,
+    /// minPINLength of the authenticator (4 if not reported)
+    pub min_pin_length: u32,
+}
+
+pub trait PinProvider {
+    fn provide_pin(&self, context: &PinContext) -> Result<Zeroizing<String>>;
+}
+
+/// Any closure taking the context can be used as a provider
+impl<F> PinProvider for F
+where
+    F: Fn(&PinContext) -> Result<Zeroizing<String>>,
+{
+    fn provide_pin(&self, context: &PinContext) -> Result<Zeroizing<String>> {
+        self(context)
+    }
+}
+
+/// Always the same PIN
+pub struct FixedPin {
+    pin: Zeroizing<String>,
+}
+
+impl FixedPin {
+    pub fn new(pin: &str) -> FixedPin {
+        FixedPin {
+            pin: Zeroizing::new(pin.to_string()),
+        }
+    }
+}
+
+impl PinProvider for FixedPin {
+    fn provide_pin(&self, _context: &PinContext) -> Result<Zeroizing<String>> {
+        Ok(self.pin.clone())
+    }
+}
+
+/// The PIN in an environment variable
+pub struct EnvPin {
+    name: String,
+}
+
+impl EnvPin {
+    pub fn new(name: &str) -> EnvPin {
+        EnvPin {
+            name: name.to_string(),
+        }
+    }
+}
+
+impl PinProvider for EnvPin {
+    fn provide_pin(&self, _context: &PinContext) -> Result<Zeroizing<String>> {
+        std::env::var(&self.name)
+            .map(Zeroizing::new)
+            .map_err(|_| anyhow!("environment variable {} is not set.", self.name))
+    }
+}
+
+/// Prompt on the terminal (the input is not echoed)
+///
+/// Requires the `terminal-pin` feature.
+#[cfg(feature = "terminal-pin")]
+#[derive(Default)]
+pub struct TerminalPin {}
+
+#[cfg(feature = "terminal-pin")]
+impl TerminalPin {
+    pub fn new() -> TerminalPin {
+        TerminalPin {}
+    }
+}
+
+#[cfg(feature = "terminal-pin")]
+impl PinProvider for TerminalPin {
+    fn provide_pin(&self, context: &PinContext) -> Result<Zeroizing<String>> {
+        let prompt = match (context.reason, context.retries) {
+            (PinReason::Verify, Some(retries)) => {
+                format!("PIN ({} retries left): ", retries)
+            }
+            (PinReason::Verify, None) => "PIN: ".to_string(),
+            (_, _) => format!("New PIN (at least {} characters): ", context.min_pin_length),
+        };
+        Ok(Zeroizing::new(rpassword::prompt_password(prompt)?))
+    }
+}
@@ -1,9 +1,48 @@
+use crate::enc_hmac_sha_256;
+use std::fmt;
+use zeroize::Zeroize;
+
+// pinUvAuthToken - zeroized on drop and not printed by Debug
 pub struct PinToken {
     pub key: Vec<u8>,
+    pub pin_uv_auth_protocol: u32,
 }
 
 impl PinToken {
     pub fn new(data: &[u8]) -> PinToken {
-        PinToken { key: data.to_vec() }
+        PinToken::with_protocol(data, 1)
+    }
+
+    pub fn with_protocol(data: &[u8], pin_uv_auth_protocol: u32) -> PinToken {
+        PinToken {
+            key: data.to_vec(),
+            pin_uv_auth_protocol,
+        }
+    }
+
+    // authenticate(pinUvAuthToken, message) → pinUvAuthParam
+    // - pinUvAuthProtocol 1 : LEFT(HMAC-SHA-256(key, message), 16)
+    // - pinUvAuthProtocol 2 : HMAC-SHA-256(key, message)
+    pub fn authenticate(&self, message: &[u8]) -> Vec<u8> {
+        let sig = enc_hmac_sha_256::authenticate(&self.key, message);
+        if self.pin_uv_auth_protocol == 2 {
+            sig
+        } else {
+            sig[0..16].to_vec()
+        }
+    }
+}
+
+impl Drop for PinToken {
+    fn drop(&mut self) {
+        self.key.zeroize();
+    }
+}
+
+impl fmt::Debug for PinToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("PinToken")
+            .field("pin_uv_auth_protocol", &self.pin_uv_auth_protocol)
+            .finish_non_exhaustive()
     }
 }
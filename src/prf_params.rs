@@ -0,0 +1,197 @@
+/*!
+WebAuthn prf extension parameters (on top of hmac-secret)
+*/
+
+use crate::get_assertion_params::Extension as Gext;
+use crate::make_credential_params::Extension as Mext;
+use crate::str_buf::StrBuf;
+use crate::util;
+use ring::digest;
+use std::collections::BTreeMap;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// AuthenticationExtensionsPRFValues
+/// [10.1.4. Pseudo-random function extension (prf)](https://w3c.github.io/webauthn/#prf-extension)
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PrfValues {
+    pub first: Vec<u8>,
+    pub second: Option<Vec<u8>>,
+}
+
+impl PrfValues {
+    pub fn new(first: &[u8], second: Option<&[u8]>) -> PrfValues {
+        PrfValues {
+            first: first.to_vec(),
+            second: second.map(|x| x.to_vec()),
+        }
+    }
+
+    /// hmac-secret salts
+    /// - salt1 = SHA-256("WebAuthn PRF" || 0x00 || first)
+    /// - salt2 = SHA-256("WebAuthn PRF" || 0x00 || second)
+    pub fn to_salts(&self) -> ([u8; 32], Option<[u8; 32]>) {
+        (
+            create_salt(&self.first),
+            self.second.as_ref().map(|x| create_salt(x)),
+        )
+    }
+
+    fn from_outputs(output1: &[u8; 32], output2: &Option<[u8; 32]>) -> PrfValues {
+        PrfValues {
+            first: output1.to_vec(),
+            second: output2.map(|x| x.to_vec()),
+        }
+    }
+}
+
+impl fmt::Display for PrfValues {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut strbuf = StrBuf::new(10);
+        strbuf.appenh("- first", &self.first);
+        if let Some(second) = &self.second {
+            strbuf.appenh("- second", second);
+        }
+        write!(f, "{}", strbuf.build())
+    }
+}
+
+/// AuthenticationExtensionsPRFInputs
+#[derive(Debug, Default, Clone)]
+pub struct PrfInputs {
+    pub eval: Option<PrfValues>,
+    /// credential id → values
+    pub eval_by_credential: BTreeMap<Vec<u8>, PrfValues>,
+}
+
+impl PrfInputs {
+    pub fn new() -> PrfInputs {
+        PrfInputs::default()
+    }
+
+    pub fn eval(mut self, values: PrfValues) -> PrfInputs {
+        self.eval = Some(values);
+        self
+    }
+
+    pub fn eval_by_credential(mut self, credential_id: &[u8], values: PrfValues) -> PrfInputs {
+        self.eval_by_credential
+            .insert(credential_id.to_vec(), values);
+        self
+    }
+
+    /// The values to evaluate for credential_id (evalByCredential takes precedence over eval)
+    pub fn select(&self, credential_id: &[u8]) -> Option<&PrfValues> {
+        self.eval_by_credential
+            .get(credential_id)
+            .or(self.eval.as_ref())
+    }
+
+    /// Check evalByCredential against the allowList
+    /// - evalByCredential requires a non-empty allowList
+    /// - every key of evalByCredential must be in the allowList
+    pub fn check_allow_list(&self, allow_list: &[Vec<u8>]) -> Result<(), String> {
+        if self.eval_by_credential.is_empty() {
+            return Ok(());
+        }
+        if allow_list.is_empty() {
+            return Err("prf: evalByCredential requires allowCredentials.".to_string());
+        }
+        for credential_id in self.eval_by_credential.keys() {
+            if !allow_list.contains(credential_id) {
+                return Err(format!(
+                    "prf: evalByCredential key {} is not in allowCredentials.",
+                    util::to_hex_str(credential_id)
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// makeCredential extensions
+    /// - hmac-secret: true
+    /// - hmac-secret-mc: salts of eval (if any and the authenticator supports hmac-secret-mc)
+    pub fn to_make_credential_extensions(&self, hmac_secret_mc: bool) -> Result<Vec<Mext>, String> {
+        if !self.eval_by_credential.is_empty() {
+            return Err("prf: evalByCredential is not allowed in registration.".to_string());
+        }
+        let mut extensions = vec![Mext::HmacSecret(Some(true))];
+        if let (Some(eval), true) = (&self.eval, hmac_secret_mc) {
+            let (salt1, salt2) = eval.to_salts();
+            extensions.push(Mext::HmacSecretMc((Some(salt1), salt2)));
+        }
+        Ok(extensions)
+    }
+
+    /// getAssertion extension for credential_id
+    pub fn to_get_assertion_extension(&self, credential_id: &[u8]) -> Option<Gext> {
+        self.select(credential_id)
+            .map(|values| match values.to_salts() {
+                (salt1, None) => Gext::HmacSecret(Some(salt1)),
+                (salt1, Some(salt2)) => Gext::HmacSecret2((Some(salt1), Some(salt2))),
+            })
+    }
+}
+
+/// AuthenticationExtensionsPRFOutputs
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PrfOutputs {
+    /// registration only - whether the credential supports prf
+    pub enabled: Option<bool>,
+    pub results: Option<PrfValues>,
+}
+
+impl PrfOutputs {
+    pub fn from_make_credential_extensions(extensions: &[Mext]) -> PrfOutputs {
+        let mut ret = PrfOutputs::default();
+        for ext in extensions {
+            match ext {
+                Mext::HmacSecret(Some(enabled)) => ret.enabled = Some(*enabled),
+                Mext::HmacSecretMc((Some(output1), output2)) => {
+                    ret.results = Some(PrfValues::from_outputs(output1, output2))
+                }
+                _ => {}
+            }
+        }
+        ret.enabled.get_or_insert(false);
+        ret
+    }
+
+    pub fn from_get_assertion_extensions(extensions: &[Gext]) -> PrfOutputs {
+        let mut ret = PrfOutputs::default();
+        for ext in extensions {
+            match ext {
+                Gext::HmacSecret(Some(output1)) => {
+                    ret.results = Some(PrfValues::from_outputs(output1, &None))
+                }
+                Gext::HmacSecret2((Some(output1), output2)) => {
+                    ret.results = Some(PrfValues::from_outputs(output1, output2))
+                }
+                _ => {}
+            }
+        }
+        ret
+    }
+}
+
+impl fmt::Display for PrfOutputs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut strbuf = StrBuf::new(10);
+        if let Some(enabled) = self.enabled {
+            strbuf.append("- enabled", &enabled);
+        }
+        if let Some(results) = &self.results {
+            strbuf.appent("- results").add(&format!("{}", results));
+        }
+        write!(f, "{}", strbuf.build())
+    }
+}
+
+// SHA-256("WebAuthn PRF" || 0x00 || input)
+fn create_salt(input: &[u8]) -> [u8; 32] {
+    let mut message = b"WebAuthn PRF".to_vec();
+    message.push(0x00);
+    message.extend_from_slice(input);
+    let hash = digest::digest(&digest::SHA256, &message);
+    <[u8; 32]>::try_from(hash.as_ref()).unwrap()
+}
@@ -16,10 +16,10 @@ impl PublicKey {
     pub fn new(cbor: &Value) -> Self {
         let cose_key = CoseKey::new(cbor).unwrap();
 
-        let mut cose_public_key = PublicKey::default();
-        cose_public_key.der = cose_key.to_public_key_der();
-        cose_public_key.pem = util::convert_to_publickey_pem(&cose_public_key.der);
-        cose_public_key
+        PublicKey {
+            der: cose_key.to_public_key_der(),
+            pem: util::convert_spki_to_pem(&cose_key.to_subject_public_key_info()),
+        }
     }
 }
 
@@ -4,14 +4,28 @@ PublicKeyCredentialDescriptor
 
 use crate::util;
 use serde_cbor::Value;
+use std::collections::BTreeMap;
 use std::fmt;
 
-#[derive(Debug, Default, Clone)]
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct PublicKeyCredentialDescriptor {
     pub id: Vec<u8>,
     pub ctype: String,
+    /// "usb", "nfc", "ble", "internal", "hybrid", ...
+    pub transports: Vec<String>,
 }
 impl PublicKeyCredentialDescriptor {
+    pub fn new(id: &[u8]) -> Self {
+        PublicKeyCredentialDescriptor {
+            id: id.to_vec(),
+            ctype: "public-key".to_string(),
+            ..Default::default()
+        }
+    }
+    pub fn transports(mut self, transports: &[&str]) -> Self {
+        self.transports = transports.iter().map(|x| x.to_string()).collect();
+        self
+    }
     pub fn get_id(self: &mut PublicKeyCredentialDescriptor, cbor: &Value) -> Self {
         let mut ret = self.clone();
         ret.id = util::cbor_get_bytes_from_map(cbor, "id").unwrap_or_default();
@@ -22,14 +36,52 @@ impl PublicKeyCredentialDescriptor {
         ret.ctype = util::cbor_get_string_from_map(cbor, "type").unwrap_or_default();
         ret
     }
+    pub(crate) fn to_value(&self) -> Value {
+        let mut map = BTreeMap::new();
+        map.insert(
+            Value::Text("id".to_string()),
+            Value::Bytes(self.id.to_vec()),
+        );
+        let ctype = if self.ctype.is_empty() {
+            "public-key"
+        } else {
+            &self.ctype
+        };
+        map.insert(
+            Value::Text("type".to_string()),
+            Value::Text(ctype.to_string()),
+        );
+        if !self.transports.is_empty() {
+            map.insert(
+                Value::Text("transports".to_string()),
+                Value::Array(
+                    self.transports
+                        .iter()
+                        .map(|x| Value::Text(x.to_string()))
+                        .collect(),
+                ),
+            );
+        }
+        Value::Map(map)
+    }
 }
 impl fmt::Display for PublicKeyCredentialDescriptor {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "(id : {} , type : {})",
-            util::to_hex_str(&self.id),
-            self.ctype
-        )
+        if self.transports.is_empty() {
+            write!(
+                f,
+                "(id : {} , type : {})",
+                util::to_hex_str(&self.id),
+                self.ctype
+            )
+        } else {
+            write!(
+                f,
+                "(id : {} , type : {} , transports : {:?})",
+                util::to_hex_str(&self.id),
+                self.ctype,
+                self.transports
+            )
+        }
     }
 }
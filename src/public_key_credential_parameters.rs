@@ -0,0 +1,35 @@
+/*!
+PublicKeyCredentialParameters
+*/
+
+use crate::util;
+use serde_cbor::Value;
+use std::fmt;
+
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct PublicKeyCredentialParameters {
+    pub ctype: String,
+    /// COSE algorithm identifier
+    pub alg: i32,
+}
+impl PublicKeyCredentialParameters {
+    pub fn get_type(self: &mut PublicKeyCredentialParameters, cbor: &Value) -> Self {
+        let mut ret = self.clone();
+        ret.ctype = util::cbor_get_string_from_map(cbor, "type").unwrap_or_default();
+        ret
+    }
+    pub fn get_alg(self: &mut PublicKeyCredentialParameters, cbor: &Value) -> Self {
+        let mut ret = self.clone();
+        if let Value::Map(xs) = cbor {
+            if let Some(val) = xs.get(&Value::Text("alg".to_string())) {
+                ret.alg = util::cbor_value_to_num(val).unwrap_or_default();
+            }
+        }
+        ret
+    }
+}
+impl fmt::Display for PublicKeyCredentialParameters {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "(type : {} , alg : {})", self.ctype, self.alg)
+    }
+}
@@ -1,20 +1,98 @@
 use cose::CoseKey;
 use ring::error::Unspecified;
-use ring::{agreement, digest, rand};
+use ring::rand::SecureRandom;
+use ring::{agreement, digest, hkdf, rand};
+use std::fmt;
+use zeroize::{Zeroize, Zeroizing};
 
 use crate::cose;
 use crate::enc_aes256_cbc;
+use crate::enc_hmac_sha_256;
 use crate::p256;
 use crate::pintoken::PinToken;
 
-#[derive(Debug, Default, Clone)]
+// The secrets are zeroized on drop and not printed by Debug
+#[derive(Default)]
 pub struct SharedSecret {
     pub public_key: CoseKey,
+    // pinUvAuthProtocol 1 : SHA-256(Z)
+    // pinUvAuthProtocol 2 : HMAC key
     pub secret: [u8; 32],
+    pub pin_uv_auth_protocol: u32,
+    // pinUvAuthProtocol 1 : SHA-256(Z)
+    // pinUvAuthProtocol 2 : AES key
+    aes_key: [u8; 32],
+}
+
+impl Drop for SharedSecret {
+    fn drop(&mut self) {
+        self.secret.zeroize();
+        self.aes_key.zeroize();
+    }
+}
+
+impl fmt::Debug for SharedSecret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SharedSecret")
+            .field("public_key", &self.public_key)
+            .field("pin_uv_auth_protocol", &self.pin_uv_auth_protocol)
+            .finish_non_exhaustive()
+    }
 }
 
 impl SharedSecret {
+    // 6.5.6. PIN/UV Auth Protocol One
     pub fn new(peer_key: &CoseKey) -> Result<Self, String> {
+        let (public_key, z) = SharedSecret::agree(peer_key)?;
+        let hash = digest::digest(&digest::SHA256, &z);
+
+        let mut res = SharedSecret::with_protocol(public_key, 1);
+        res.secret.copy_from_slice(hash.as_ref());
+        res.aes_key.copy_from_slice(hash.as_ref());
+
+        Ok(res)
+    }
+
+    // 6.5.7. PIN/UV Auth Protocol Two
+    pub fn new_v2(peer_key: &CoseKey) -> Result<Self, String> {
+        let (public_key, z) = SharedSecret::agree(peer_key)?;
+
+        // HKDF-SHA-256(salt = 32 zero bytes, IKM = Z, L = 32, info = "CTAP2 HMAC key" / "CTAP2 AES key")
+        let prk = hkdf::Salt::new(hkdf::HKDF_SHA256, &[0u8; 32]).extract(&z);
+        let expand = |info: &[u8], out: &mut [u8; 32]| {
+            prk.expand(&[info], hkdf::HKDF_SHA256)
+                .and_then(|okm| okm.fill(out))
+                .map_err(|_| "HKDF error".to_string())
+        };
+
+        let mut res = SharedSecret::with_protocol(public_key, 2);
+        expand(b"CTAP2 HMAC key", &mut res.secret)?;
+        expand(b"CTAP2 AES key", &mut res.aes_key)?;
+
+        Ok(res)
+    }
+
+    pub fn new_with_protocol(
+        peer_key: &CoseKey,
+        pin_uv_auth_protocol: u32,
+    ) -> Result<Self, String> {
+        match pin_uv_auth_protocol {
+            2 => SharedSecret::new_v2(peer_key),
+            _ => SharedSecret::new(peer_key),
+        }
+    }
+
+    fn with_protocol(public_key: CoseKey, pin_uv_auth_protocol: u32) -> Self {
+        SharedSecret {
+            public_key,
+            secret: [0; 32],
+            pin_uv_auth_protocol,
+            aes_key: [0; 32],
+        }
+    }
+
+    // ECDH → (platform key agreement key, Z)
+    fn agree(peer_key: &CoseKey) -> Result<(CoseKey, Zeroizing<Vec<u8>>), String> {
         let rng = rand::SystemRandom::new();
         let my_private_key =
             agreement::EphemeralPrivateKey::generate(&agreement::ECDH_P256, &rng).unwrap();
@@ -22,25 +100,18 @@ impl SharedSecret {
         let my_public_key = my_private_key.compute_public_key().unwrap();
 
         let peer_public_key = {
-            let peer_public_key = p256::P256Key::from_cose(peer_key).unwrap().bytes();
+            let peer_public_key = p256::P256Key::from_cose(peer_key)?.bytes();
             agreement::UnparsedPublicKey::new(&agreement::ECDH_P256, peer_public_key)
         };
 
-        let shared_secret =
+        let z =
             agreement::agree_ephemeral(my_private_key, &peer_public_key, Unspecified, |material| {
-                Ok(digest::digest(&digest::SHA256, material))
+                Ok(Zeroizing::new(material.to_vec()))
             })
-            .unwrap();
+            .map_err(|_| "ECDH error".to_string())?;
 
-        let mut res = SharedSecret {
-            public_key: p256::P256Key::from_bytes(my_public_key.as_ref())
-                .unwrap()
-                .to_cose(),
-            secret: [0; 32],
-        };
-        res.secret.copy_from_slice(shared_secret.as_ref());
-
-        Ok(res)
+        let public_key = p256::P256Key::from_bytes(my_public_key.as_ref())?.to_cose();
+        Ok((public_key, z))
     }
 
     pub fn encrypt_pin(&self, pin: &str) -> Result<[u8; 16], String> {
@@ -57,8 +128,71 @@ impl SharedSecret {
     }
 
     pub fn decrypt_token(&self, data: &mut [u8]) -> Result<PinToken, String> {
-        let dec = enc_aes256_cbc::decrypt_message(&self.secret, data);
-        let pin_token = PinToken::new(&dec);
-        Ok(pin_token)
+        let dec = if self.pin_uv_auth_protocol == 2 {
+            self.decrypt_message(data)?
+        } else {
+            enc_aes256_cbc::decrypt_message(&self.secret, data)
+        };
+        Ok(PinToken {
+            key: dec,
+            pin_uv_auth_protocol: self.pin_uv_auth_protocol,
+        })
+    }
+
+    // encrypt(key, demPlaintext) → ciphertext
+    // - pinUvAuthProtocol 1 : AES-256-CBC(key, IV=0, demPlaintext)
+    // - pinUvAuthProtocol 2 : IV || AES-256-CBC(key, IV=random, demPlaintext)
+    pub fn encrypt_message(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+        if self.pin_uv_auth_protocol == 2 {
+            let mut iv = [0u8; 16];
+            rand::SystemRandom::new()
+                .fill(&mut iv)
+                .map_err(|_| "random error".to_string())?;
+            let mut ret = iv.to_vec();
+            ret.append(&mut enc_aes256_cbc::encrypt_message_with_iv(
+                &self.aes_key,
+                &iv,
+                message,
+            ));
+            Ok(ret)
+        } else {
+            Ok(enc_aes256_cbc::encrypt_message(&self.aes_key, message))
+        }
+    }
+
+    // decrypt(key, ciphertext) → plaintext
+    pub fn decrypt_message(&self, message: &[u8]) -> Result<Vec<u8>, String> {
+        // the ciphertext must be whole AES blocks
+        let min_len = if self.pin_uv_auth_protocol == 2 {
+            32
+        } else {
+            16
+        };
+        if message.len() < min_len || message.len() / 16 * 16 != message.len() {
+            return Err("ciphertext length error".to_string());
+        }
+        if self.pin_uv_auth_protocol == 2 {
+            let mut iv = [0u8; 16];
+            iv.copy_from_slice(&message[0..16]);
+            Ok(enc_aes256_cbc::decrypt_message_with_iv(
+                &self.aes_key,
+                &iv,
+                &message[16..],
+            ))
+        } else {
+            Ok(enc_aes256_cbc::decrypt_message(&self.aes_key, message))
+        }
+    }
+
+    // authenticate(key, message) → signature
+    // - pinUvAuthProtocol 1 : LEFT(HMAC-SHA-256(key, message), 16)
+    // - pinUvAuthProtocol 2 : HMAC-SHA-256(key, message)
+    pub fn authenticate(&self, message: &[u8]) -> Vec<u8> {
+        let sig = enc_hmac_sha_256::authenticate(&self.secret, message);
+        if self.pin_uv_auth_protocol == 2 {
+            sig
+        } else {
+            sig[0..16].to_vec()
+        }
     }
 }
@@ -0,0 +1,293 @@
+use crate::auth_data::{AttestedCredentialData, AuthenticatorData, Flags};
+use crate::ctaphid;
+use crate::get_assertion_params::{Assertion, NoCredentials};
+use crate::get_assertion_response;
+use crate::make_credential_params::{Attestation, CredentialExcluded};
+use crate::make_credential_response;
+use crate::public_key_credential_descriptor::PublicKeyCredentialDescriptor;
+use crate::u2f_command;
+use crate::u2f_params::{AuthenticateControl, AuthenticateResponse, RegisterResponse};
+use crate::u2f_response;
+use crate::util;
+use crate::FidoKeyHid;
+use anyhow::{anyhow, Error, Result};
+use ring::digest;
+use serde_cbor::Value;
+use std::collections::BTreeMap;
+use std::{thread, time};
+
+// SW_CONDITIONS_NOT_SATISFIED (0x6985)
+const SW1_CONDITIONS_NOT_SATISFIED: u8 = 0x69;
+// SW_WRONG_DATA (0x6A80)
+const SW1_WRONG_DATA: u8 = 0x6A;
+
+// test-of-user-presence polling
+const USER_PRESENCE_POLLING_INTERVAL_MS: u64 = 100;
+const USER_PRESENCE_TIMEOUT_MS: u64 = 30_000;
+
+// Resend the request while the authenticator answers SW_CONDITIONS_NOT_SATISFIED,
+// i.e. until the user touches the key.
+fn send_apdu_with_user_presence(
+    device: &FidoKeyHid,
+    cid: &[u8],
+    ins: u8,
+    p1: u8,
+    data: &[u8],
+) -> Result<Vec<u8>> {
+    let mut keep_alive_msg_flag = false;
+    let mut elapsed = 0;
+    loop {
+        match ctaphid::send_apdu(device, cid, 0, ins, p1, 0, data) {
+            Ok(response) => return Ok(response),
+            Err(err) if err.is_status(SW1_CONDITIONS_NOT_SATISFIED) => {
+                if elapsed >= USER_PRESENCE_TIMEOUT_MS {
+                    return Err(anyhow!("u2f user presence timeout. ({})", err));
+                }
+                if !keep_alive_msg_flag {
+                    if !device.keep_alive_msg.is_empty() {
+                        println!("{}", device.keep_alive_msg);
+                    }
+                    keep_alive_msg_flag = true;
+                }
+                thread::sleep(time::Duration::from_millis(
+                    USER_PRESENCE_POLLING_INTERVAL_MS,
+                ));
+                elapsed += USER_PRESENCE_POLLING_INTERVAL_MS;
+            }
+            Err(err) => return Err(anyhow!(err)),
+        }
+    }
+}
+
+pub fn register(
+    device: &FidoKeyHid,
+    app_param: &[u8],
+    challenge: &[u8],
+) -> Result<RegisterResponse> {
+    let data = u2f_command::create_register_data(challenge, app_param).map_err(Error::msg)?;
+
+    let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+    let response = send_apdu_with_user_presence(device, &cid, u2f_command::U2F_REGISTER, 0, &data)?;
+
+    u2f_response::parse_register(&response).map_err(Error::msg)
+}
+
+pub fn check_only(device: &FidoKeyHid, app_param: &[u8], key_handle: &[u8]) -> Result<bool> {
+    // the challenge parameter is not used by check-only
+    let data = u2f_command::create_authenticate_data(&[0u8; 32], app_param, key_handle)
+        .map_err(Error::msg)?;
+
+    let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+    match ctaphid::send_apdu(
+        device,
+        &cid,
+        0,
+        u2f_command::U2F_AUTHENTICATE,
+        u2f_command::CONTROL_CHECK_ONLY,
+        0,
+        &data,
+    ) {
+        // SW_CONDITIONS_NOT_SATISFIED : the key handle was created by this key for the application parameter
+        Err(err) if err.is_status(SW1_CONDITIONS_NOT_SATISFIED) => Ok(true),
+        // SW_WRONG_DATA : invalid key handle
+        Err(err) if err.is_status(SW1_WRONG_DATA) => Ok(false),
+        Err(err) => Err(anyhow!(err)),
+        Ok(_) => Ok(true),
+    }
+}
+
+pub fn authenticate(
+    device: &FidoKeyHid,
+    control: AuthenticateControl,
+    app_param: &[u8],
+    challenge: &[u8],
+    key_handle: &[u8],
+) -> Result<AuthenticateResponse> {
+    let data = u2f_command::create_authenticate_data(challenge, app_param, key_handle)
+        .map_err(Error::msg)?;
+
+    let p1 = match control {
+        AuthenticateControl::EnforceUserPresence => u2f_command::CONTROL_ENFORCE_USER_PRESENCE,
+        AuthenticateControl::DontEnforceUserPresence => {
+            u2f_command::CONTROL_DONT_ENFORCE_USER_PRESENCE
+        }
+    };
+
+    let cid = ctaphid::ctaphid_init(device).map_err(Error::msg)?;
+    let response =
+        send_apdu_with_user_presence(device, &cid, u2f_command::U2F_AUTHENTICATE, p1, &data)?;
+
+    u2f_response::parse_authenticate(&response).map_err(Error::msg)
+}
+
+// the credentials whose ID can be sent as a key handle
+pub(crate) fn key_handles(
+    list: &[PublicKeyCredentialDescriptor],
+) -> impl Iterator<Item = &PublicKeyCredentialDescriptor> {
+    list.iter()
+        .filter(|x| u2f_command::is_valid_key_handle(&x.id))
+}
+
+// CTAP1/U2F-only authenticator - CTAPHID_INIT does not report CAPABILITY_CBOR
+pub(crate) fn is_ctap1_only(capabilities: u8) -> bool {
+    capabilities & ctaphid::CAPABILITY_CBOR == 0
+}
+
+// 10.3.1. Using the CTAP2 authenticatorMakeCredential Command with CTAP1/U2F authenticators
+// https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-20210615.html#u2f-authenticatorMakeCredential-interoperability
+pub fn make_credential(
+    device: &FidoKeyHid,
+    rpid: &str,
+    challenge: &[u8],
+    exclude_list: &[PublicKeyCredentialDescriptor],
+) -> Result<Attestation> {
+    let app_param = digest::digest(&digest::SHA256, rpid.as_bytes());
+    let client_data_hash = util::create_clientdata_hash(challenge.to_vec());
+
+    // excludeList - check-only for each credential
+    // (a credential ID that does not fit in a key handle cannot belong to this authenticator)
+    for credential in key_handles(exclude_list) {
+        if check_only(device, app_param.as_ref(), &credential.id)? {
+            // wait for the user to touch the key with a bogus registration before failing
+            register(device, &[0x41; 32], &[0x42; 32])?;
+            return Err(Error::new(CredentialExcluded {
+                credential_id: Some(credential.id.to_vec()),
+            }));
+        }
+    }
+
+    let reg = register(device, app_param.as_ref(), &client_data_hash)?;
+
+    // authenticatorData - UP and AT, signCount = 0, aaguid = 0
+    let auth_data = AuthenticatorData {
+        rpid_hash: app_param.as_ref().to_vec(),
+        flags: Flags {
+            user_present_result: true,
+            ..Default::default()
+        },
+        sign_count: 0,
+        attested_credential_data: Some(AttestedCredentialData {
+            aaguid: vec![0; 16],
+            credential_id: reg.key_handle.to_vec(),
+            credential_public_key: cose_es256_key(&reg.user_public_key)?,
+        }),
+        extensions: BTreeMap::new(),
+    };
+
+    // attestation statement - fido-u2f
+    let mut att_stmt = BTreeMap::new();
+    att_stmt.insert(Value::Text("sig".to_string()), Value::Bytes(reg.signature));
+    att_stmt.insert(
+        Value::Text("x5c".to_string()),
+        Value::Array(vec![Value::Bytes(reg.attestation_certificate)]),
+    );
+
+    // authenticatorMakeCredential response
+    let mut response = BTreeMap::new();
+    response.insert(Value::Integer(0x01), Value::Text("fido-u2f".to_string()));
+    response.insert(Value::Integer(0x02), Value::Bytes(auth_data.serialize()?));
+    response.insert(Value::Integer(0x03), Value::Map(att_stmt));
+    let response_cbor = serde_cbor::to_vec(&Value::Map(response))?;
+
+    make_credential_response::parse_cbor(&response_cbor, None).map_err(Error::msg)
+}
+
+// 10.3.2. Using the CTAP2 authenticatorGetAssertion Command with CTAP1/U2F authenticators
+// https://fidoalliance.org/specs/fido-v2.1-ps-20210615/fido-client-to-authenticator-protocol-v2.1-ps-20210615.html#u2f-authenticatorGetAssertion-interoperability
+pub fn get_assertion(
+    device: &FidoKeyHid,
+    rpid: &str,
+    challenge: &[u8],
+    allow_list: &[PublicKeyCredentialDescriptor],
+    up: bool,
+) -> Result<Assertion> {
+    if allow_list.is_empty() {
+        return Err(anyhow!(
+            "CTAP1/U2F authenticator requires an allowList. (discoverable credentials are not supported)"
+        ));
+    }
+
+    let app_param = digest::digest(&digest::SHA256, rpid.as_bytes());
+    let client_data_hash = util::create_clientdata_hash(challenge.to_vec());
+
+    // the first credential of the allowList created by this authenticator
+    let mut credential = None;
+    for x in key_handles(allow_list) {
+        if check_only(device, app_param.as_ref(), &x.id)? {
+            credential = Some(x);
+            break;
+        }
+    }
+    let credential = credential.ok_or_else(|| Error::new(NoCredentials))?;
+
+    let control = if up {
+        AuthenticateControl::EnforceUserPresence
+    } else {
+        AuthenticateControl::DontEnforceUserPresence
+    };
+    let auth = authenticate(
+        device,
+        control,
+        app_param.as_ref(),
+        &client_data_hash,
+        &credential.id,
+    )?;
+
+    // authenticatorData - rpIdHash || flags(user presence byte) || counter
+    let auth_data = AuthenticatorData {
+        rpid_hash: app_param.as_ref().to_vec(),
+        flags: Flags {
+            user_present_result: auth.user_presence,
+            ..Default::default()
+        },
+        sign_count: auth.counter,
+        ..Default::default()
+    };
+
+    // authenticatorGetAssertion response
+    let mut descriptor = BTreeMap::new();
+    descriptor.insert(
+        Value::Text("id".to_string()),
+        Value::Bytes(credential.id.to_vec()),
+    );
+    descriptor.insert(
+        Value::Text("type".to_string()),
+        Value::Text("public-key".to_string()),
+    );
+    let mut response = BTreeMap::new();
+    response.insert(Value::Integer(0x01), Value::Map(descriptor));
+    response.insert(Value::Integer(0x02), Value::Bytes(auth_data.serialize()?));
+    response.insert(Value::Integer(0x03), Value::Bytes(auth.signature));
+    let response_cbor = serde_cbor::to_vec(&Value::Map(response))?;
+
+    let mut ass = get_assertion_response::parse_cbor(&response_cbor, None).map_err(Error::msg)?;
+    ass.number_of_credentials = 1;
+    Ok(ass)
+}
+
+// COSE_Key of the U2F user public key (0x04 || x || y)
+pub(crate) fn cose_es256_key(user_public_key: &[u8]) -> Result<Value> {
+    if user_public_key.len() != 65 || user_public_key[0] != 0x04 {
+        return Err(anyhow!(
+            "u2f user public key is not an uncompressed P-256 point"
+        ));
+    }
+
+    let mut key = BTreeMap::new();
+    // kty : EC2
+    key.insert(Value::Integer(1), Value::Integer(2));
+    // alg : ES256
+    key.insert(Value::Integer(3), Value::Integer(-7));
+    // crv : P-256
+    key.insert(Value::Integer(-1), Value::Integer(1));
+    // x, y
+    key.insert(
+        Value::Integer(-2),
+        Value::Bytes(user_public_key[1..33].to_vec()),
+    );
+    key.insert(
+        Value::Integer(-3),
+        Value::Bytes(user_public_key[33..65].to_vec()),
+    );
+    Ok(Value::Map(key))
+}
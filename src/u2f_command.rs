@@ -0,0 +1,54 @@
+// CTAP1/U2F instructions (INS)
+pub const U2F_REGISTER: u8 = 0x01;
+pub const U2F_AUTHENTICATE: u8 = 0x02;
+pub const U2F_VERSION: u8 = 0x03;
+
+// U2F_AUTHENTICATE control byte (P1)
+pub const CONTROL_CHECK_ONLY: u8 = 0x07;
+pub const CONTROL_ENFORCE_USER_PRESENCE: u8 = 0x03;
+pub const CONTROL_DONT_ENFORCE_USER_PRESENCE: u8 = 0x08;
+
+// key handle length is one byte
+pub const MAX_KEY_HANDLE_LENGTH: usize = 255;
+
+pub fn is_valid_key_handle(key_handle: &[u8]) -> bool {
+    !key_handle.is_empty() && key_handle.len() <= MAX_KEY_HANDLE_LENGTH
+}
+
+fn check_param(name: &str, param: &[u8]) -> Result<(), String> {
+    if param.len() != 32 {
+        return Err(format!("{} must be 32 bytes. ({})", name, param.len()));
+    }
+    Ok(())
+}
+
+// Registration Request Message
+// - challenge parameter(32) || application parameter(32)
+pub fn create_register_data(challenge: &[u8], app_param: &[u8]) -> Result<Vec<u8>, String> {
+    check_param("challenge parameter", challenge)?;
+    check_param("application parameter", app_param)?;
+
+    let mut data = challenge.to_vec();
+    data.extend_from_slice(app_param);
+    Ok(data)
+}
+
+// Authentication Request Message
+// - challenge parameter(32) || application parameter(32) || key handle length(1) || key handle
+pub fn create_authenticate_data(
+    challenge: &[u8],
+    app_param: &[u8],
+    key_handle: &[u8],
+) -> Result<Vec<u8>, String> {
+    check_param("challenge parameter", challenge)?;
+    check_param("application parameter", app_param)?;
+    if !is_valid_key_handle(key_handle) {
+        return Err(format!("key handle length error. ({})", key_handle.len()));
+    }
+
+    let mut data = challenge.to_vec();
+    data.extend_from_slice(app_param);
+    data.push(key_handle.len() as u8);
+    data.extend_from_slice(key_handle);
+    Ok(data)
+}
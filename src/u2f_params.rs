@@ -0,0 +1,74 @@
+/*!
+U2F(CTAP1) API parameters
+*/
+
+use crate::public_key::PublicKey;
+use crate::str_buf::StrBuf;
+use crate::util;
+use std::fmt;
+
+/// U2F_AUTHENTICATE control byte
+/// - check-only is done with [`u2f_check_only`](crate::u2f_check_only)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AuthenticateControl {
+    /// enforce-user-presence-and-sign (0x03) - waits for the user to touch the key
+    #[default]
+    EnforceUserPresence,
+    /// dont-enforce-user-presence-and-sign (0x08)
+    DontEnforceUserPresence,
+}
+
+/// Registration Response Message
+/// [FIDO U2F Raw Message Formats 4.3](https://fidoalliance.org/specs/fido-u2f-v1.2-ps-20170411/fido-u2f-raw-message-formats-v1.2-ps-20170411.html#registration-response-message-success)
+#[derive(Debug, Default, Clone)]
+pub struct RegisterResponse {
+    /// uncompressed P-256 point (0x04 || x || y)
+    pub user_public_key: Vec<u8>,
+    pub key_handle: Vec<u8>,
+    /// X.509 certificate in DER format
+    pub attestation_certificate: Vec<u8>,
+    /// ECDSA signature (ASN.1 DER) over 0x00 || application parameter || challenge parameter || key handle || user public key
+    pub signature: Vec<u8>,
+}
+
+impl RegisterResponse {
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey {
+            der: self.user_public_key.to_vec(),
+            pem: util::convert_to_publickey_pem(&self.user_public_key),
+        }
+    }
+}
+
+impl fmt::Display for RegisterResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut strbuf = StrBuf::new(30);
+        strbuf
+            .appenh("- user_public_key", &self.user_public_key)
+            .appenh("- key_handle", &self.key_handle)
+            .appenh("- attestation_certificate", &self.attestation_certificate)
+            .appenh("- signature", &self.signature);
+        write!(f, "{}", strbuf.build())
+    }
+}
+
+/// Authentication Response Message
+/// [FIDO U2F Raw Message Formats 5.4](https://fidoalliance.org/specs/fido-u2f-v1.2-ps-20170411/fido-u2f-raw-message-formats-v1.2-ps-20170411.html#authentication-response-message-success)
+#[derive(Debug, Default, Clone)]
+pub struct AuthenticateResponse {
+    pub user_presence: bool,
+    pub counter: u32,
+    /// ECDSA signature (ASN.1 DER) over application parameter || user presence || counter || challenge parameter
+    pub signature: Vec<u8>,
+}
+
+impl fmt::Display for AuthenticateResponse {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut strbuf = StrBuf::new(30);
+        strbuf
+            .append("- user_presence", &self.user_presence)
+            .append("- counter", &self.counter)
+            .appenh("- signature", &self.signature);
+        write!(f, "{}", strbuf.build())
+    }
+}
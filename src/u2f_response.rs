@@ -0,0 +1,68 @@
+use crate::u2f_params::{AuthenticateResponse, RegisterResponse};
+
+// length of a DER element (tag || length || value)
+fn der_element_length(bytes: &[u8]) -> Result<usize, String> {
+    if bytes.len() < 2 {
+        return Err("attestation certificate parse error".to_string());
+    }
+    let (header, length) = if bytes[1] & 0x80 == 0 {
+        (2, bytes[1] as usize)
+    } else {
+        let num = (bytes[1] & 0x7f) as usize;
+        if num == 0 || num > 4 || bytes.len() < 2 + num {
+            return Err("attestation certificate parse error".to_string());
+        }
+        let length = bytes[2..2 + num]
+            .iter()
+            .fold(0usize, |acc, b| (acc << 8) | *b as usize);
+        (2 + num, length)
+    };
+    Ok(header + length)
+}
+
+// Registration Response Message
+// - reserved(0x05) || user public key(65) || key handle length(1) || key handle
+//   || attestation certificate || signature
+pub fn parse_register(bytes: &[u8]) -> Result<RegisterResponse, String> {
+    if bytes.len() < 67 || bytes[0] != 0x05 {
+        return Err("u2f register response parse error".to_string());
+    }
+
+    let user_public_key = bytes[1..66].to_vec();
+    let key_handle_length = bytes[66] as usize;
+    let rest = &bytes[67..];
+    if rest.len() < key_handle_length {
+        return Err("u2f register response parse error".to_string());
+    }
+    let (key_handle, rest) = rest.split_at(key_handle_length);
+
+    let cert_length = der_element_length(rest)?;
+    if rest.len() < cert_length {
+        return Err("attestation certificate parse error".to_string());
+    }
+    let (attestation_certificate, signature) = rest.split_at(cert_length);
+
+    Ok(RegisterResponse {
+        user_public_key,
+        key_handle: key_handle.to_vec(),
+        attestation_certificate: attestation_certificate.to_vec(),
+        signature: signature.to_vec(),
+    })
+}
+
+// Authentication Response Message
+// - user presence(1) || counter(4, big-endian) || signature
+pub fn parse_authenticate(bytes: &[u8]) -> Result<AuthenticateResponse, String> {
+    if bytes.len() < 5 {
+        return Err("u2f authenticate response parse error".to_string());
+    }
+
+    let mut counter = [0u8; 4];
+    counter.copy_from_slice(&bytes[1..5]);
+
+    Ok(AuthenticateResponse {
+        user_presence: bytes[0] & 0x01 != 0,
+        counter: u32::from_be_bytes(counter),
+        signature: bytes[5..].to_vec(),
+    })
+}
@@ -183,6 +183,7 @@ pub(crate) fn create_clientdata_hash(challenge: Vec<u8>) -> Vec<u8> {
     hasher.as_ref().to_vec()
 }
 
+// P-256 public key (0x04 || x || y) → PEM
 #[allow(dead_code)]
 pub(crate) fn convert_to_publickey_pem(public_key_der: &[u8]) -> String {
     let mut tmp = vec![];
@@ -197,8 +198,17 @@ pub(crate) fn convert_to_publickey_pem(public_key_der: &[u8]) -> String {
 
     tmp.append(&mut public_key_der.to_vec());
 
+    convert_spki_to_pem(&tmp)
+}
+
+// SubjectPublicKeyInfo → PEM
+pub(crate) fn convert_spki_to_pem(spki: &[u8]) -> String {
+    if spki.is_empty() {
+        return "".to_string();
+    }
+
     // 1.encode Base64
-    let base64_str = base64::encode(tmp);
+    let base64_str = base64::encode(spki);
 
     // 2. /n　every 64 characters
     let pem_base = {
@@ -219,3 +229,44 @@ pub(crate) fn convert_to_publickey_pem(public_key_der: &[u8]) -> String {
     // 3. Header and footer
     "-----BEGIN PUBLIC KEY-----\n".to_string() + &pem_base + &"-----END PUBLIC KEY-----".to_string()
 }
+
+// DER tag || length || contents
+pub(crate) fn der_tlv(tag: u8, contents: &[u8]) -> Vec<u8> {
+    let mut tlv = vec![tag];
+    let len = contents.len();
+    if len < 0x80 {
+        tlv.push(len as u8);
+    } else {
+        let len_bytes: Vec<u8> = len
+            .to_be_bytes()
+            .iter()
+            .skip_while(|&&b| b == 0)
+            .copied()
+            .collect();
+        tlv.push(0x80 | len_bytes.len() as u8);
+        tlv.extend_from_slice(&len_bytes);
+    }
+    tlv.extend_from_slice(contents);
+    tlv
+}
+
+// OBJECT IDENTIFIER (hex encoded contents)
+pub(crate) fn der_oid(oid_hex: &str) -> Vec<u8> {
+    der_tlv(0x06, &hex::decode(oid_hex).unwrap())
+}
+
+// AlgorithmIdentifier ::= SEQUENCE { id-ecPublicKey, namedCurve }
+pub(crate) fn der_ec_algorithm(curve_oid_hex: &str) -> Vec<u8> {
+    let mut seq = der_oid("2a8648ce3d0201");
+    seq.append(&mut der_oid(curve_oid_hex));
+    der_tlv(0x30, &seq)
+}
+
+// INTEGER from big-endian unsigned bytes
+pub(crate) fn der_unsigned_integer(bytes: &[u8]) -> Vec<u8> {
+    let mut int: Vec<u8> = bytes.iter().skip_while(|&&b| b == 0).copied().collect();
+    if int.is_empty() || int[0] & 0x80 != 0 {
+        int.insert(0, 0x00);
+    }
+    der_tlv(0x02, &int)
+}
@@ -2,6 +2,7 @@
 Verify Attestation Assertion API
 */
 
+use crate::auth_data::Flags;
 use crate::get_assertion_params;
 use crate::make_credential_params;
 use crate::util;
@@ -25,6 +26,23 @@ pub struct AttestationVerifyResult {
     pub credential_id: Vec<u8>,
     pub credential_publickey_pem: String,
     pub credential_publickey_der: Vec<u8>,
+    /// BE - the credential can be backed up (multi-device credential)
+    pub backup_eligibility: bool,
+    /// BS - the credential is currently backed up
+    pub backup_state: bool,
+}
+
+/// Assertion Verify Result
+#[derive(Debug, Default)]
+pub struct AssertionVerifyResult {
+    pub is_success: bool,
+    pub user_present: bool,
+    pub user_verified: bool,
+    /// BE - the credential can be backed up (multi-device credential)
+    pub backup_eligibility: bool,
+    /// BS - the credential is currently backed up
+    pub backup_state: bool,
+    pub sign_count: u32,
 }
 
 /// Verify Atterstaion Object
@@ -33,7 +51,7 @@ pub fn verify_attestation(
     challenge: &[u8],
     attestation: &make_credential_params::Attestation,
 ) -> AttestationVerifyResult {
-    if !verify_rpid(rpid, &attestation.rpid_hash) {
+    if !verify_rpid(rpid, &attestation.rpid_hash) || !verify_backup_flags(&attestation.flags) {
         return AttestationVerifyResult::default();
     }
 
@@ -54,18 +72,24 @@ pub fn verify_attestation(
     };
 
     // Verify the signature.
-    let result = verify_sig(
-        public_key.as_ref(),
-        challenge,
-        &attestation.auth_data,
-        &attestation.attstmt_sig,
-    );
+    let result = if attestation.fmt == "fido-u2f" {
+        verify_sig_u2f(public_key.as_ref(), challenge, attestation)
+    } else {
+        verify_sig(
+            public_key.as_ref(),
+            challenge,
+            &attestation.auth_data,
+            &attestation.attstmt_sig,
+        )
+    };
 
     AttestationVerifyResult {
         is_success: result,
         credential_id: attestation.credential_descriptor.id.to_vec(),
         credential_publickey_pem: attestation.credential_publickey.pem.to_string(),
         credential_publickey_der: attestation.credential_publickey.der.to_vec(),
+        backup_eligibility: attestation.flags.backup_eligibility,
+        backup_state: attestation.flags.backup_state,
     }
 }
 
@@ -76,18 +100,37 @@ pub fn verify_assertion(
     challenge: &[u8],
     assertion: &get_assertion_params::Assertion,
 ) -> bool {
+    verify_assertion_with_result(rpid, publickey, challenge, assertion).is_success
+}
+
+/// Verify Assertion Object and return the authenticator data flags
+pub fn verify_assertion_with_result(
+    rpid: &str,
+    publickey: &[u8],
+    challenge: &[u8],
+    assertion: &get_assertion_params::Assertion,
+) -> AssertionVerifyResult {
     // Verify rpid
-    if !verify_rpid(rpid, &assertion.rpid_hash) {
-        return false;
+    if !verify_rpid(rpid, &assertion.rpid_hash) || !verify_backup_flags(&assertion.flags) {
+        return AssertionVerifyResult::default();
     }
 
     // Verify the signature.
-    verify_sig(
+    let result = verify_sig(
         publickey,
         challenge,
         &assertion.auth_data,
         &assertion.signature,
-    )
+    );
+
+    AssertionVerifyResult {
+        is_success: result,
+        user_present: assertion.flags.user_present_result,
+        user_verified: assertion.flags.user_verified_result,
+        backup_eligibility: assertion.flags.backup_eligibility,
+        backup_state: assertion.flags.backup_state,
+        sign_count: assertion.sign_count,
+    }
 }
 
 fn verify_sig(public_key_der: &[u8], challenge: &[u8], auth_data: &[u8], sig: &[u8]) -> bool {
@@ -119,6 +162,37 @@ fn verify_sig(public_key_der: &[u8], challenge: &[u8], auth_data: &[u8], sig: &[
     }
 }
 
+// fido-u2f attestation statement
+// verificationData = 0x00 || rpIdHash || clientDataHash || credentialId || publicKeyU2F
+fn verify_sig_u2f(
+    public_key_der: &[u8],
+    challenge: &[u8],
+    attestation: &make_credential_params::Attestation,
+) -> bool {
+    let message = {
+        let mut base: Vec<u8> = vec![0x00];
+        base.append(&mut attestation.rpid_hash.to_vec());
+
+        let cdh = digest::digest(&digest::SHA256, challenge);
+        base.append(&mut cdh.as_ref().to_vec());
+
+        base.append(&mut attestation.credential_descriptor.id.to_vec());
+        base.append(&mut attestation.credential_publickey.der.to_vec());
+        base
+    };
+
+    let peer_public_key =
+        signature::UnparsedPublicKey::new(&signature::ECDSA_P256_SHA256_ASN1, public_key_der);
+    peer_public_key
+        .verify(&message, &attestation.attstmt_sig)
+        .is_ok()
+}
+
+// BS must not be set unless BE is set
+fn verify_backup_flags(flags: &Flags) -> bool {
+    flags.backup_eligibility || !flags.backup_state
+}
+
 fn verify_rpid(rpid: &str, rpid_hash: &[u8]) -> bool {
     // SHA-256(rpid) == attestation.RpIdHash
     let rpid_hash_comp = {